@@ -36,7 +36,7 @@ async fn main(_spawner: Spawner) {
     let dry_reading = calibrate_sensor(&mut saadc, &mut button).await;
 
     // We want something a little less than the dry reading to trigger watering
-    let moisture_threshold = dry_reading - THRESHOLD_BUFFER;
+    let moisture_threshold = dry_reading.saturating_sub(THRESHOLD_BUFFER);
 
     loop {
         let button_press = button.wait_for_low();
@@ -1,15 +1,22 @@
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
+
 use debouncer::Debouncer;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_nrf::{
     bind_interrupts,
     gpio::{Input, Level, Output, OutputDrive, Pin, Pull},
     saadc::{self, ChannelConfig, Config, Saadc},
 };
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
-use embassy_time::Duration;
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use planty_core::bus::{Bus, Priority, Push};
 
 mod debouncer;
 
@@ -25,7 +32,7 @@ const DEFAULT_THRESHOLD: i16 = 2000;
 const THRESHOLD_BUFFER: i16 = 100;
 
 // Transitions
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Event {
     Water,
     WateringComplete,
@@ -35,11 +42,37 @@ enum Event {
 
 #[derive(Debug, PartialEq)]
 enum SystemState {
-    Watering,
+    /// Automatic watering stops by itself at `until`, manual watering when
+    /// button A is released.
+    Watering {
+        until: Option<Instant>,
+    },
     Idle,
 }
 
-static CHANNEL: Channel<ThreadModeRawMutex, Event, 1> = Channel::new();
+/// Pending events per priority, see `planty_core::bus`. Sending never waits,
+/// so a stop is never stuck behind a backlog of measurements.
+static BUS: Mutex<ThreadModeRawMutex, RefCell<Bus<Event, 4>>> =
+    Mutex::new(RefCell::new(Bus::new()));
+static READY: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+fn send(priority: Priority, event: Event) {
+    match BUS.lock(|bus| bus.borrow_mut().push(priority, event)) {
+        Push::Queued => READY.signal(()),
+        Push::Coalesced => {}
+        Push::Dropped => defmt::warn!("Event queue full, dropping {:?}", event),
+    }
+}
+
+/// Waits for the most urgent pending event.
+async fn receive() -> Event {
+    loop {
+        if let Some(event) = BUS.lock(|bus| bus.borrow_mut().pop()) {
+            return event;
+        }
+        READY.wait().await;
+    }
+}
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
@@ -69,30 +102,28 @@ async fn main(spawner: Spawner) {
 
 #[embassy_executor::task]
 async fn button_a_task(mut button: Debouncer<'static>) {
-    let sender = CHANNEL.sender();
     loop {
         button.debounce().await;
-        sender.send(Event::Water).await;
+        send(Priority::User, Event::Water);
         button.debounce().await;
-        sender.send(Event::WateringComplete).await;
+        // Letting go stops the pump, which must not wait behind anything
+        send(Priority::Safety, Event::WateringComplete);
     }
 }
 
 #[embassy_executor::task]
 async fn button_b_task(mut button: Debouncer<'static>) {
-    let sender = CHANNEL.sender();
     loop {
         button.debounce().await;
-        sender.send(Event::Calibrate).await;
+        send(Priority::User, Event::Calibrate);
     }
 }
 
 #[embassy_executor::task]
 async fn measurement_task() {
-    let sender = CHANNEL.sender();
     loop {
-        embassy_time::Timer::after(MEASUREMENT_INTERVAL).await;
-        sender.send(Event::Measure).await;
+        Timer::after(MEASUREMENT_INTERVAL).await;
+        send(Priority::Periodic, Event::Measure);
     }
 }
 
@@ -101,25 +132,37 @@ async fn control_task(mut pump_control: Output<'static>, mut saadc: Saadc<'stati
     defmt::info!("System started. Press button A to manually water.");
     defmt::info!("System started. Press button B to calibrate.");
 
-    let receiver = CHANNEL.receiver();
-
     // Start with a default threshold
     let mut moisture_threshold = DEFAULT_THRESHOLD;
     let mut system_state = SystemState::Idle;
 
     // https://www.youtube.com/watch?v=z-0-bbc80JM
     loop {
-        let event = receiver.receive().await;
+        // Keep handling events while watering, so a stop is never held up
+        let deadline = match system_state {
+            SystemState::Watering { until } => until,
+            SystemState::Idle => None,
+        };
+        let deadline = async move {
+            match deadline {
+                Some(at) => Timer::at(at).await,
+                None => core::future::pending().await,
+            }
+        };
+        let event = match select(receive(), deadline).await {
+            Either::First(event) => event,
+            Either::Second(()) => Event::WateringComplete,
+        };
 
         system_state = match (system_state, event) {
             // Handle watering state transitions
             (SystemState::Idle, Event::Water) => {
                 defmt::info!("Watering requested");
                 pump_control.set_high();
-                SystemState::Watering
+                SystemState::Watering { until: None }
             }
 
-            (SystemState::Watering, Event::WateringComplete) => {
+            (SystemState::Watering { .. }, Event::WateringComplete) => {
                 defmt::info!("Watering complete");
                 pump_control.set_low();
                 SystemState::Idle
@@ -130,16 +173,22 @@ async fn control_task(mut pump_control: Output<'static>, mut saadc: Saadc<'stati
                 defmt::info!("Taking moisture reading");
                 let reading = read_moisture(&mut saadc).await;
                 defmt::info!("Moisture reading: {}", reading);
+                let counters = BUS.lock(|bus| bus.borrow().counters());
+                defmt::info!(
+                    "Events dropped: {}, coalesced: {}",
+                    counters.dropped,
+                    counters.coalesced
+                );
 
                 if reading > moisture_threshold {
                     defmt::info!("Soil is dry, watering");
                     pump_control.set_high();
-                    embassy_time::Timer::after(WATERING_DURATION).await;
-                    pump_control.set_low();
-                    defmt::info!("Automatic watering complete");
+                    SystemState::Watering {
+                        until: Some(Instant::now() + WATERING_DURATION),
+                    }
+                } else {
+                    SystemState::Idle
                 }
-
-                SystemState::Idle
             }
 
             // Handle calibration
@@ -149,7 +198,7 @@ async fn control_task(mut pump_control: Output<'static>, mut saadc: Saadc<'stati
                 let dry_reading = read_moisture(&mut saadc).await;
                 defmt::info!("Dry reading: {}", dry_reading);
 
-                moisture_threshold = dry_reading - THRESHOLD_BUFFER;
+                moisture_threshold = dry_reading.saturating_sub(THRESHOLD_BUFFER);
                 defmt::info!(
                     "Calibration complete. New threshold: {}",
                     moisture_threshold
@@ -1,7 +1,32 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  /* NRF52840 with Softdevice S140 7.3.0 */
-  FLASH : ORIGIN = 0x00000000 + 156K, LENGTH = 1024K - 156K
-  RAM : ORIGIN = 0x20000000 + 31K, LENGTH = 256K - 31K
-}
\ No newline at end of file
+  /* nRF52833 with Softdevice S140 7.3.0 and the bootloader in src/bootloader */
+  MBR_SOFTDEVICE   : ORIGIN = 0x00000000, LENGTH = 156K
+  FLASH            : ORIGIN = 0x00027000, LENGTH = 160K
+  DFU              : ORIGIN = 0x0004F000, LENGTH = 164K
+  BOOTLOADER       : ORIGIN = 0x00078000, LENGTH = 28K
+  BOOTLOADER_STATE : ORIGIN = 0x0007F000, LENGTH = 4K
+  /* What the SoftDevice needs with the configuration in ble.rs, roughly 6K
+     base, 4K attribute table and up to 6K per link for 3 links at a 247 byte
+     MTU, plus headroom. `Softdevice::enable` logs the exact start
+     `sd_ble_enable` asks for: it panics if this is too low and warns about
+     wasted bytes if it is too high. Change both together. */
+  RAM              : ORIGIN = 0x20000000 + 36K, LENGTH = 128K - 36K - 256
+  /* The last panic, kept across resets. Must match bootloader/memory.x */
+  CRASH            : ORIGIN = 0x20020000 - 256, LENGTH = 256
+}
+
+SECTIONS
+{
+  .crash (NOLOAD) : ALIGN(4)
+  {
+    KEEP(*(.crash .crash.*));
+  } > CRASH
+} INSERT AFTER .uninit;
+
+__bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
+__bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
+
+__bootloader_dfu_start = ORIGIN(DFU);
+__bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
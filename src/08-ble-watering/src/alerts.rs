@@ -0,0 +1,75 @@
+//! Plays alerts on the speaker, unless muted or within quiet hours. The
+//! melodies and the rules for when they may sound are in
+//! `planty_core::alerts`.
+//!
+//! The board has no clock, so quiet hours only apply once a BLE client has
+//! written the time of day. Settings and time are lost on reset.
+
+use core::cell::Cell;
+
+use embassy_nrf::peripherals::PWM0;
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    channel::Channel,
+};
+use embassy_time::Instant;
+use microbit_bsp::speaker::{Note, Pitch, PwmSpeaker};
+use planty_core::alerts::{Alert, Settings, Tone, MINUTES_PER_DAY};
+
+pub type Speaker = PwmSpeaker<'static, PWM0>;
+
+static ALERTS: Channel<ThreadModeRawMutex, Alert, 2> = Channel::new();
+static SETTINGS: Mutex<ThreadModeRawMutex, Cell<Settings>> = Mutex::new(Cell::new(Settings::new()));
+/// The minute of the day a client reported, and when.
+static CLOCK: Mutex<ThreadModeRawMutex, Cell<Option<(Instant, u16)>>> = Mutex::new(Cell::new(None));
+
+/// Queues an alert without waiting. Alerts are dropped while two are pending.
+pub fn play(alert: Alert) {
+    if ALERTS.try_send(alert).is_err() {
+        defmt::warn!(
+            "Alert queue full, dropping {:?}",
+            defmt::Debug2Format(&alert)
+        );
+    }
+}
+
+pub fn settings() -> Settings {
+    SETTINGS.lock(Cell::get)
+}
+
+pub fn set_settings(settings: Settings) {
+    SETTINGS.lock(|cell| cell.set(settings));
+}
+
+pub fn set_time_of_day(minute_of_day: u16) {
+    CLOCK.lock(|clock| clock.set(Some((Instant::now(), minute_of_day % MINUTES_PER_DAY))));
+}
+
+fn minute_of_day() -> Option<u16> {
+    let (set_at, minute) = CLOCK.lock(Cell::get)?;
+    let minutes = minute as u64 + set_at.elapsed().as_secs() / 60;
+    Some((minutes % MINUTES_PER_DAY as u64) as u16)
+}
+
+fn note(tone: &Tone) -> Note {
+    let pitch = match tone.frequency_hz {
+        0 => Pitch::Silent,
+        frequency_hz => Pitch::Frequency(frequency_hz as u32),
+    };
+    Note(pitch, tone.duration_ms as u32)
+}
+
+#[embassy_executor::task]
+pub async fn alert_task(mut speaker: Speaker) {
+    loop {
+        let alert = ALERTS.receive().await;
+        if !settings().allows(minute_of_day()) {
+            defmt::info!("Alert {:?} silenced", defmt::Debug2Format(&alert));
+            continue;
+        }
+
+        for tone in alert.melody() {
+            speaker.play(&note(tone)).await;
+        }
+    }
+}
@@ -1,17 +1,58 @@
+use core::cell::{Cell, RefCell};
+
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    signal::Signal,
+};
+use heapless::Vec;
 use nrf_softdevice::{
-    ble::advertisement_builder::{
-        Flag, LegacyAdvertisementBuilder, LegacyAdvertisementPayload, ServiceList,
+    ble::{
+        advertisement_builder::{
+            AdvertisementDataType, Flag, LegacyAdvertisementBuilder, LegacyAdvertisementPayload,
+            ServiceList,
+        },
+        gatt_server, peripheral, Connection,
     },
-    Softdevice,
+    raw, Softdevice,
+};
+use planty_core::{
+    alerts::{Alert, Settings, MINUTES_PER_DAY},
+    beacon,
+    bus::{Counters, Push},
+    outbox::{Coalesce, Outbox},
+    probe::Health,
+    pump::{self, Ack, Outcome, Request},
+    temperature::{self, Compensation},
+};
+use static_cell::StaticCell;
+
+use crate::{
+    alerts,
+    connections::{Connections, Subscription, MAX_CONNECTIONS},
+    crash, dfu,
+    events::{self, Priority, Source},
+    power, sensor,
+    watchdog::{self, Task},
 };
 
 const DEVICE_NAME: &str = "planty";
 
-pub static ADV_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
-    .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
-    .full_name(DEVICE_NAME)
-    .build();
-pub static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
+/// Advertisement data carrying the latest plant status, rebuilt after every
+/// measurement.
+fn adv_payload(status: &beacon::Status) -> LegacyAdvertisementPayload {
+    LegacyAdvertisementBuilder::new()
+        .flags(&[Flag::GeneralDiscovery, Flag::LE_Only])
+        .full_name(DEVICE_NAME)
+        .raw(
+            AdvertisementDataType::MANUFACTURER_SPECIFIC_DATA,
+            &status.encode(),
+        )
+        .build()
+}
+
+static SCAN_DATA: LegacyAdvertisementPayload = LegacyAdvertisementBuilder::new()
     .services_128(
         ServiceList::Complete,
         &[0x12345678_1234_5678_1234_56789abcdef0_u128.to_le_bytes()],
@@ -29,14 +70,514 @@ pub struct PlantService {
 
     #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef2", read, notify)]
     pub moisture_level: u16,
+
+    /// Supply voltage in millivolts, measured with every moisture reading.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef3", read, notify)]
+    pub supply_voltage: u16,
+
+    /// RESETREAS as read at boot, zero after a power-on or brownout.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef4", read)]
+    pub reset_reason: u32,
+
+    /// Mute and quiet hours, laid out as in `planty_core::alerts::Settings`.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef5", read, write)]
+    pub alert_settings: [u8; Settings::LEN],
+
+    /// Local time in minutes since midnight, for the quiet hours.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef6", write)]
+    pub time_of_day: u16,
+
+    /// What became of the last pump control write, laid out as in
+    /// `planty_core::pump::Ack`.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef7", read, notify)]
+    pub pump_status: [u8; Ack::LEN],
+
+    /// How the last moisture reading was classified, as the discriminant of
+    /// `planty_core::probe::Health`. Zero when the probe is fine.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef8", read, notify)]
+    pub sensor_health: u8,
+
+    /// Die temperature in hundredths of a degree Celsius, measured with every
+    /// moisture reading.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdef9", read, notify)]
+    pub temperature: i16,
+
+    /// Temperature compensation applied to moisture readings, in hundredths
+    /// of a count per degree Celsius; see `planty_core::temperature`.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdefa", read, write)]
+    pub temperature_compensation: i16,
+
+    /// The compensation the readings since the last watering suggest, or
+    /// `planty_core::temperature::NO_ESTIMATE` until there are enough.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdefb", read)]
+    pub compensation_estimate: i16,
+}
+
+#[nrf_softdevice::gatt_service(uuid = "12345678-1234-5678-1234-56789abcdf00")]
+pub struct DfuService {
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdf01", write)]
+    pub control: Vec<u8, { 5 + dfu::SIGNATURE_LEN }>,
+
+    #[characteristic(
+        uuid = "12345678-1234-5678-1234-56789abcdf02",
+        write,
+        write_without_response
+    )]
+    pub data: Vec<u8, { dfu::MAX_CHUNK_LEN + 4 }>,
+
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdf03", read, notify)]
+    pub status: [u8; dfu::Status::LEN],
+}
+
+#[nrf_softdevice::gatt_service(uuid = "12345678-1234-5678-1234-56789abcdf10")]
+pub struct DiagnosticsService {
+    /// The last panic, encoded as described in `planty_core::crash`. Any write
+    /// clears it.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdf11", read, write)]
+    pub crash_record: Vec<u8, { crash::ENCODED_LEN }>,
+
+    /// Events the controller dropped or coalesced since boot, laid out as in
+    /// `planty_core::bus::Counters`.
+    #[characteristic(uuid = "12345678-1234-5678-1234-56789abcdf12", read)]
+    pub event_counters: [u8; Counters::LEN],
 }
 
 #[nrf_softdevice::gatt_server]
 pub struct Server {
     pub plant_service: PlantService,
+    pub dfu_service: DfuService,
+    pub diagnostics_service: DiagnosticsService,
+}
+
+/// Room for the attributes of all three services. The default of 1408 bytes
+/// doesn't fit: about 45 attributes, the DFU chunk, crash record and DFU
+/// control values alone take close to 500 bytes. Raising it moves the RAM the
+/// SoftDevice needs, and so the start of RAM in `memory.x`.
+const ATTR_TAB_SIZE: u32 = 4096;
+
+/// Softdevice configuration with room for `MAX_CONNECTIONS` peripheral links.
+pub fn softdevice_config() -> nrf_softdevice::Config {
+    nrf_softdevice::Config {
+        conn_gap: Some(raw::ble_gap_conn_cfg_t {
+            conn_count: MAX_CONNECTIONS as u8,
+            event_length: 24,
+        }),
+        gap_role_count: Some(raw::ble_gap_cfg_role_count_t {
+            adv_set_count: 1,
+            periph_role_count: MAX_CONNECTIONS as u8,
+            central_role_count: 0,
+            central_sec_count: 0,
+            _bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
+        }),
+        // Large enough for a full DFU data chunk in a single write
+        conn_gatt: Some(raw::ble_gatt_conn_cfg_t { att_mtu: 247 }),
+        gatts_attr_tab_size: Some(raw::ble_gatts_cfg_attr_tab_size_t {
+            attr_tab_size: ATTR_TAB_SIZE,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Updates published by the controller. The BLE layer applies them to the
+/// GATT server and advertisements; the controller never touches either.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Update {
+    Moisture(u16),
+    SupplyVoltage(u16),
+    ResetReason(u32),
+    Advertisement(beacon::Status),
+    DfuStatus(dfu::Status),
+    PumpStatus(Ack),
+    SensorHealth(Health),
+    /// In quarter degrees Celsius.
+    Temperature(i32),
+    CompensationEstimate(Option<Compensation>),
+    EventCounters(Counters),
+}
+
+impl Coalesce for Update {
+    fn kind(&self) -> Option<usize> {
+        match self {
+            Update::Moisture(_) => Some(0),
+            Update::SupplyVoltage(_) => Some(1),
+            Update::ResetReason(_) => Some(2),
+            Update::Advertisement(_) => Some(3),
+            Update::DfuStatus(_) => Some(4),
+            // Every command is acknowledged
+            Update::PumpStatus(_) => None,
+            Update::SensorHealth(_) => Some(5),
+            Update::Temperature(_) => Some(6),
+            Update::CompensationEstimate(_) => Some(7),
+            Update::EventCounters(_) => Some(8),
+        }
+    }
+}
+
+const UPDATE_KINDS: usize = 9;
+/// Room for an acknowledgement of every event the controller can have
+/// queued, so none is dropped even while nobody is connected.
+const PENDING_ACKS: usize = 2 * events::CAPACITY;
+
+static UPDATES: Mutex<ThreadModeRawMutex, RefCell<Outbox<Update, UPDATE_KINDS, PENDING_ACKS>>> =
+    Mutex::new(RefCell::new(Outbox::new()));
+static UPDATES_READY: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+static SERVER: StaticCell<Server> = StaticCell::new();
+static CONNECTIONS: Connections = Connections::new();
+static ADVERTISEMENT_SIGNAL: Signal<ThreadModeRawMutex, beacon::Status> = Signal::new();
+/// Sequence number of the last pump command, shared by all connections.
+static SEQUENCE: Mutex<ThreadModeRawMutex, Cell<u8>> = Mutex::new(Cell::new(0));
+
+/// Queues an update for the BLE layer without waiting. While BLE falls
+/// behind, an update replaces the pending one of its kind; see
+/// `planty_core::outbox`.
+pub fn publish(update: Update) {
+    if UPDATES.lock(|updates| updates.borrow_mut().push(update)) == Push::Dropped {
+        defmt::error!("BLE update queue full, dropping pump acknowledgement");
+    }
+    UPDATES_READY.signal(());
+}
+
+pub fn connection_count() -> usize {
+    CONNECTIONS.count()
+}
+
+/// Creates the GATT server. It is only ever handed to the BLE tasks below.
+pub fn init_server(
+    softdevice: &mut Softdevice,
+) -> Result<&'static Server, gatt_server::RegisterError> {
+    let server = SERVER.init(Server::new(softdevice)?);
+    let estimate = temperature::NO_ESTIMATE;
+    if let Err(error) = server.plant_service.compensation_estimate_set(&estimate) {
+        defmt::warn!("Failed to set compensation estimate: {:?}", error);
+    }
+    if let Some(record) = crash::record() {
+        set_crash_record(server, &crash::encode(&record));
+    }
+    Ok(server)
 }
 
 #[embassy_executor::task]
 pub async fn softdevice_task(sd: &'static Softdevice) -> ! {
     sd.run().await
 }
+
+/// Applies controller updates to the GATT server and advertisements.
+#[embassy_executor::task]
+pub async fn update_task(server: &'static Server) {
+    loop {
+        UPDATES_READY.wait().await;
+        while let Some(update) = UPDATES.lock(|updates| updates.borrow_mut().pop()) {
+            apply(server, update);
+        }
+    }
+}
+
+fn apply(server: &Server, update: Update) {
+    match update {
+        Update::Moisture(reading) => update_moisture_level(server, reading),
+        Update::SupplyVoltage(millivolts) => update_supply_voltage(server, millivolts),
+        Update::ResetReason(reason) => {
+            if let Err(error) = server.plant_service.reset_reason_set(&reason) {
+                defmt::warn!("Failed to set reset reason: {:?}", error);
+            }
+        }
+        Update::Advertisement(status) => ADVERTISEMENT_SIGNAL.signal(status),
+        Update::DfuStatus(status) => update_dfu_status(server, status),
+        Update::PumpStatus(ack) => update_pump_status(server, ack),
+        Update::SensorHealth(health) => update_sensor_health(server, health),
+        Update::Temperature(quarter_celsius) => update_temperature(server, quarter_celsius),
+        Update::CompensationEstimate(estimate) => {
+            let estimate = temperature::encode_estimate(estimate);
+            if let Err(error) = server.plant_service.compensation_estimate_set(&estimate) {
+                defmt::warn!("Failed to set compensation estimate: {:?}", error);
+            }
+        }
+        Update::EventCounters(counters) => {
+            let counters = counters.encode();
+            if let Err(error) = server.diagnostics_service.event_counters_set(&counters) {
+                defmt::warn!("Failed to set event counters: {:?}", error);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn ble_task(spawner: Spawner, softdevice: &'static Softdevice, server: &'static Server) {
+    let mut status = beacon::Status::default();
+
+    loop {
+        // Only advertise while there is room for another central
+        watchdog::idle(Task::Ble, CONNECTIONS.wait_for_free_slot()).await;
+
+        let config = advertising_config();
+
+        let adv_data = adv_payload(&status);
+        let advertisement = peripheral::advertise_connectable(
+            softdevice,
+            peripheral::ConnectableAdvertisement::ScannableUndirected {
+                adv_data: &adv_data,
+                scan_data: &SCAN_DATA,
+            },
+            &config,
+        );
+
+        // Restart advertising whenever there is a fresh status to broadcast
+        let advertisement = select(advertisement, ADVERTISEMENT_SIGNAL.wait());
+        let connection = match watchdog::idle(Task::Ble, advertisement).await {
+            Either::First(Ok(connection)) => connection,
+            Either::First(Err(error)) => {
+                defmt::warn!("Advertisement error: {:?}", error);
+                continue;
+            }
+            Either::Second(new_status) => {
+                status = new_status;
+                continue;
+            }
+        };
+
+        let Some(handle) = CONNECTIONS.add(&connection) else {
+            defmt::warn!("No free connection slot, dropping connection");
+            continue;
+        };
+
+        if let Err(error) = spawner.spawn(connection_task(server, connection, handle)) {
+            defmt::warn!("Failed to spawn connection task: {:?}", error);
+            CONNECTIONS.remove(handle);
+            continue;
+        }
+        alerts::play(Alert::BlePaired);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn beacon_task(softdevice: &'static Softdevice) {
+    let mut status = beacon::Status::default();
+
+    loop {
+        let config = advertising_config();
+        let adv_data = adv_payload(&status);
+        let advertisement = peripheral::advertise(
+            softdevice,
+            peripheral::NonconnectableAdvertisement::ScannableUndirected {
+                adv_data: &adv_data,
+                scan_data: &SCAN_DATA,
+            },
+            &config,
+        );
+
+        let advertisement = select(advertisement, ADVERTISEMENT_SIGNAL.wait());
+        match watchdog::idle(Task::Ble, advertisement).await {
+            Either::First(Ok(())) => {}
+            Either::First(Err(error)) => defmt::warn!("Advertisement error: {:?}", error),
+            Either::Second(new_status) => status = new_status,
+        }
+    }
+}
+
+#[embassy_executor::task(pool_size = MAX_CONNECTIONS)]
+async fn connection_task(server: &'static Server, connection: Connection, handle: u16) {
+    defmt::info!(
+        "Connection {} established ({}/{} centrals)",
+        handle,
+        CONNECTIONS.count(),
+        MAX_CONNECTIONS
+    );
+
+    let _disconnected = gatt_server::run(&connection, server, |event| match event {
+        ServerEvent::PlantService(evt) => match evt {
+            PlantServiceEvent::PumpControlWrite(value) => {
+                let request = Request {
+                    sequence: SEQUENCE.lock(|sequence| {
+                        sequence.set(sequence.get().wrapping_add(1));
+                        sequence.get()
+                    }),
+                    command: pump::Command::decode(value),
+                };
+                let priority = match request.command {
+                    pump::Command::Stop => Priority::Safety,
+                    pump::Command::Start => Priority::User,
+                };
+                // Acknowledged right away, so it always goes out before the
+                // controller's acknowledgement through `publish`
+                let event = request.command.into();
+                let ack = if events::send(priority, Source::Ble(request), event) {
+                    request.ack(Outcome::Queued)
+                } else {
+                    request.ack(Outcome::QueueFull)
+                };
+                update_pump_status(server, ack);
+            }
+            PlantServiceEvent::MoistureLevelCccdWrite { notifications } => {
+                defmt::info!(
+                    "Connection {} moisture level notifications: {}",
+                    handle,
+                    notifications
+                );
+                CONNECTIONS.set_notifications(handle, Subscription::MoistureLevel, notifications);
+            }
+            PlantServiceEvent::SupplyVoltageCccdWrite { notifications } => {
+                CONNECTIONS.set_notifications(handle, Subscription::SupplyVoltage, notifications);
+            }
+            PlantServiceEvent::PumpStatusCccdWrite { notifications } => {
+                CONNECTIONS.set_notifications(handle, Subscription::PumpStatus, notifications);
+            }
+            PlantServiceEvent::SensorHealthCccdWrite { notifications } => {
+                CONNECTIONS.set_notifications(handle, Subscription::SensorHealth, notifications);
+            }
+            PlantServiceEvent::TemperatureCccdWrite { notifications } => {
+                CONNECTIONS.set_notifications(handle, Subscription::Temperature, notifications);
+            }
+            PlantServiceEvent::TemperatureCompensationWrite(centicounts_per_celsius) => {
+                defmt::info!(
+                    "Temperature compensation set to {} centicounts per degree C",
+                    centicounts_per_celsius
+                );
+                sensor::set_compensation(Compensation {
+                    centicounts_per_celsius,
+                });
+            }
+            PlantServiceEvent::AlertSettingsWrite(value) => match Settings::decode(&value) {
+                Some(settings) => alerts::set_settings(settings),
+                None => {
+                    defmt::warn!("Rejected alert settings {:?}", value);
+                    let current = alerts::settings().encode();
+                    if let Err(error) = server.plant_service.alert_settings_set(&current) {
+                        defmt::warn!("Failed to restore alert settings: {:?}", error);
+                    }
+                }
+            },
+            PlantServiceEvent::TimeOfDayWrite(minute) => {
+                if minute < MINUTES_PER_DAY {
+                    alerts::set_time_of_day(minute);
+                } else {
+                    defmt::warn!("Rejected time of day {}", minute);
+                }
+            }
+        },
+        ServerEvent::DfuService(evt) => match evt {
+            DfuServiceEvent::ControlWrite(value) => dfu::control_written(&value),
+            DfuServiceEvent::DataWrite(value) => dfu::data_written(&value),
+            DfuServiceEvent::StatusCccdWrite { notifications } => {
+                CONNECTIONS.set_notifications(handle, Subscription::DfuStatus, notifications);
+            }
+        },
+        ServerEvent::DiagnosticsService(evt) => match evt {
+            DiagnosticsServiceEvent::CrashRecordWrite(_) => {
+                defmt::info!("Connection {} cleared the crash record", handle);
+                crash::clear();
+                set_crash_record(server, &Vec::new());
+            }
+        },
+    })
+    .await;
+
+    CONNECTIONS.remove(handle);
+    defmt::info!("Connection {} disconnected", handle);
+}
+
+/// Advertises less often as the battery drains. Advertising restarts after
+/// every measurement, which is also when the power mode can change.
+fn advertising_config() -> peripheral::Config {
+    peripheral::Config {
+        interval: power::mode().advertising_interval(),
+        ..Default::default()
+    }
+}
+
+/// Stores the reading in the moisture level characteristic so reads stay fresh,
+/// then notifies every connection that subscribed to it. A failed notification
+/// (typically a link that just dropped) is logged and otherwise ignored.
+fn update_moisture_level(server: &Server, reading: u16) {
+    if let Err(error) = server.plant_service.moisture_level_set(&reading) {
+        defmt::warn!("Failed to set moisture level: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::MoistureLevel, |connection| {
+        if let Err(error) = server
+            .plant_service
+            .moisture_level_notify(connection, &reading)
+        {
+            defmt::warn!("Failed to notify moisture level: {:?}", error);
+        }
+    });
+}
+
+fn update_supply_voltage(server: &Server, millivolts: u16) {
+    if let Err(error) = server.plant_service.supply_voltage_set(&millivolts) {
+        defmt::warn!("Failed to set supply voltage: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::SupplyVoltage, |connection| {
+        if let Err(error) = server
+            .plant_service
+            .supply_voltage_notify(connection, &millivolts)
+        {
+            defmt::warn!("Failed to notify supply voltage: {:?}", error);
+        }
+    });
+}
+
+fn update_pump_status(server: &Server, ack: Ack) {
+    let status = ack.encode();
+    if let Err(error) = server.plant_service.pump_status_set(&status) {
+        defmt::warn!("Failed to set pump status: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::PumpStatus, |connection| {
+        if let Err(error) = server.plant_service.pump_status_notify(connection, &status) {
+            defmt::warn!("Failed to notify pump status: {:?}", error);
+        }
+    });
+}
+
+fn update_sensor_health(server: &Server, health: Health) {
+    let health = health as u8;
+    if let Err(error) = server.plant_service.sensor_health_set(&health) {
+        defmt::warn!("Failed to set sensor health: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::SensorHealth, |connection| {
+        if let Err(error) = server
+            .plant_service
+            .sensor_health_notify(connection, &health)
+        {
+            defmt::warn!("Failed to notify sensor health: {:?}", error);
+        }
+    });
+}
+
+fn update_temperature(server: &Server, quarter_celsius: i32) {
+    let centidegrees = (quarter_celsius * 25).clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+    if let Err(error) = server.plant_service.temperature_set(&centidegrees) {
+        defmt::warn!("Failed to set temperature: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::Temperature, |connection| {
+        if let Err(error) = server
+            .plant_service
+            .temperature_notify(connection, &centidegrees)
+        {
+            defmt::warn!("Failed to notify temperature: {:?}", error);
+        }
+    });
+}
+
+fn set_crash_record(server: &Server, encoded: &Vec<u8, { crash::ENCODED_LEN }>) {
+    if let Err(error) = server.diagnostics_service.crash_record_set(encoded) {
+        defmt::warn!("Failed to set crash record: {:?}", error);
+    }
+}
+
+fn update_dfu_status(server: &Server, status: dfu::Status) {
+    let status = status.encode();
+    if let Err(error) = server.dfu_service.status_set(&status) {
+        defmt::warn!("Failed to set DFU status: {:?}", error);
+    }
+
+    CONNECTIONS.for_each_subscriber(Subscription::DfuStatus, |connection| {
+        if let Err(error) = server.dfu_service.status_notify(connection, &status) {
+            defmt::warn!("Failed to notify DFU status: {:?}", error);
+        }
+    });
+}
@@ -0,0 +1,62 @@
+//! Buttons A and B, recognized as gestures by `planty_core::gestures`:
+//!
+//! | gesture       | action                                   |
+//! |---------------|------------------------------------------|
+//! | hold A        | water while held                         |
+//! | press A       | measure now                              |
+//! | press B       | wake the display and scroll the reading  |
+//! | hold B        | calibrate the threshold to the soil now  |
+//! | A and B       | factory reset                            |
+
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_time::Instant;
+use planty_core::{
+    gestures::{Button, Gesture},
+    tasks::{self, gesture_event, Edges},
+};
+
+use crate::{
+    debouncer::Debouncer,
+    display,
+    events::{self, Source},
+    reset,
+    watchdog::{self, Task},
+};
+
+/// Debounced level changes with the time the button moved.
+static EDGES: Edges<ThreadModeRawMutex, 4> = Edges::new();
+
+/// Reports every debounced level change of one button. Runs on its own, so
+/// recognizing gestures never cancels a debounce halfway.
+#[embassy_executor::task(pool_size = 2)]
+pub async fn edge_task(button: Button, mut debouncer: Debouncer<'static>) {
+    let task = match button {
+        Button::A => Task::ButtonA,
+        Button::B => Task::ButtonB,
+    };
+    loop {
+        let edge = watchdog::idle(task, debouncer.next()).await;
+        // The buttons pull low when pressed
+        EDGES
+            .send((button, !edge.high, Instant::from_millis(edge.at)))
+            .await;
+    }
+}
+
+#[embassy_executor::task]
+pub async fn gesture_task() {
+    tasks::gestures(&EDGES, || watchdog::heartbeat(Task::Gestures), act).await
+}
+
+fn act(gesture: Gesture) {
+    defmt::info!("Gesture {:?}", defmt::Debug2Format(&gesture));
+    match gesture {
+        Gesture::Chord => reset::factory_reset(),
+        Gesture::Short(Button::B) => display::wake(),
+        _ => {
+            if let Some((priority, event)) = gesture_event(gesture) {
+                events::send(priority, Source::Button, event);
+            }
+        }
+    }
+}
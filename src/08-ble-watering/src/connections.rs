@@ -0,0 +1,120 @@
+use core::cell::RefCell;
+
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    watch::Watch,
+};
+use heapless::Vec;
+use nrf_softdevice::ble::Connection;
+
+/// Maximum number of centrals (phones, dashboards, loggers) connected at once.
+pub const MAX_CONNECTIONS: usize = 3;
+
+/// Number of tasks that may watch the connection count at the same time.
+const WATCHERS: usize = 2;
+
+/// Characteristics a central can enable notifications for.
+#[derive(Clone, Copy)]
+pub enum Subscription {
+    MoistureLevel = 1 << 0,
+    DfuStatus = 1 << 1,
+    SupplyVoltage = 1 << 2,
+    PumpStatus = 1 << 3,
+    SensorHealth = 1 << 4,
+    Temperature = 1 << 5,
+}
+
+struct Client {
+    handle: u16,
+    connection: Connection,
+    subscriptions: u8,
+}
+
+/// Every central currently connected to the plant, along with what it has
+/// subscribed to.
+///
+/// Nothing here ever blocks on BLE: the controller queries the registry when
+/// it has something to publish, and advertising watches the connection count
+/// instead of consuming a one-shot signal.
+pub struct Connections {
+    clients: Mutex<ThreadModeRawMutex, RefCell<Vec<Client, MAX_CONNECTIONS>>>,
+    count: Watch<ThreadModeRawMutex, usize, WATCHERS>,
+}
+
+impl Connections {
+    pub const fn new() -> Self {
+        Self {
+            clients: Mutex::new(RefCell::new(Vec::new())),
+            count: Watch::new_with(0),
+        }
+    }
+
+    /// Registers a freshly established connection and returns its handle, or
+    /// `None` if every slot is already taken or the link already dropped.
+    pub fn add(&self, connection: &Connection) -> Option<u16> {
+        let handle = connection.handle()?;
+
+        self.clients.lock(|clients| {
+            let mut clients = clients.borrow_mut();
+            let added = clients.push(Client {
+                handle,
+                connection: connection.clone(),
+                subscriptions: 0,
+            });
+            self.count.sender().send(clients.len());
+            added.ok().map(|_| handle)
+        })
+    }
+
+    /// Forgets a connection. The handle has to be the one captured when the
+    /// connection was added, since a disconnected `Connection` no longer has one.
+    pub fn remove(&self, handle: u16) {
+        self.clients.lock(|clients| {
+            let mut clients = clients.borrow_mut();
+            clients.retain(|client| client.handle != handle);
+            self.count.sender().send(clients.len());
+        });
+    }
+
+    pub fn set_notifications(&self, handle: u16, subscription: Subscription, enabled: bool) {
+        self.clients.lock(|clients| {
+            if let Some(client) = clients
+                .borrow_mut()
+                .iter_mut()
+                .find(|client| client.handle == handle)
+            {
+                if enabled {
+                    client.subscriptions |= subscription as u8;
+                } else {
+                    client.subscriptions &= !(subscription as u8);
+                }
+            }
+        });
+    }
+
+    /// Calls `f` for every connection that enabled notifications for `subscription`.
+    pub fn for_each_subscriber(&self, subscription: Subscription, mut f: impl FnMut(&Connection)) {
+        self.clients.lock(|clients| {
+            clients
+                .borrow()
+                .iter()
+                .filter(|client| client.subscriptions & subscription as u8 != 0)
+                .for_each(|client| f(&client.connection));
+        });
+    }
+
+    /// Number of connected centrals, without waiting.
+    pub fn count(&self) -> usize {
+        self.count.try_get().unwrap_or(0)
+    }
+
+    /// Waits until there is room for another connection.
+    pub async fn wait_for_free_slot(&self) {
+        match self.count.receiver() {
+            Some(mut count) => {
+                count.get_and(|&count| count < MAX_CONNECTIONS).await;
+            }
+            None => defmt::warn!("No free connection watcher, not waiting for a slot"),
+        }
+    }
+}
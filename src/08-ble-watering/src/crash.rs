@@ -0,0 +1,72 @@
+//! Crash record kept across resets.
+//!
+//! The panic handler writes the message, location and uptime into the
+//! `CRASH` region of RAM, then resets. The record stays until a BLE client
+//! clears it. Its encoding, and the check that rules out garbage after a
+//! power cycle, are in `planty_core::crash`, which `planty-cli crash` decodes
+//! it with too.
+
+use core::{mem::MaybeUninit, panic::PanicInfo, ptr};
+
+use heapless::Vec;
+use planty_core::crash::SLOT_LEN;
+
+pub use planty_core::crash::{Record, ENCODED_LEN};
+
+/// Left alone by the runtime and the bootloader alike, see `memory.x`.
+#[link_section = ".crash"]
+static mut SLOT: MaybeUninit<[u8; SLOT_LEN]> = MaybeUninit::uninit();
+
+/// The record left by the last panic, if any.
+pub fn record() -> Option<Record> {
+    let slot = unsafe { ptr::read_volatile(ptr::addr_of!(SLOT).cast::<[u8; SLOT_LEN]>()) };
+    Record::load(&slot)
+}
+
+/// The characteristic value for `record`.
+pub fn encode(record: &Record) -> Vec<u8, ENCODED_LEN> {
+    let (encoded, len) = record.encode();
+    // Never longer than the capacity
+    Vec::from_slice(&encoded[..len]).unwrap_or_default()
+}
+
+pub fn clear() {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(SLOT).cast::<u32>(), 0) };
+}
+
+/// Logs the record left by the last panic, if any.
+pub fn report() {
+    if let Some(record) = record() {
+        defmt::warn!(
+            "Crashed after {} ms at {}:{}: {}",
+            record.uptime_ms,
+            record.file(),
+            record.line,
+            record.message()
+        );
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    defmt::error!("{}", defmt::Display2Format(info));
+
+    let (file, line) = info
+        .location()
+        .map_or(("", 0), |location| (location.file(), location.line()));
+    let record = Record::new(
+        embassy_time::Instant::now().as_millis(),
+        file,
+        line,
+        format_args!("{}", info.message()),
+    );
+
+    unsafe {
+        ptr::write_volatile(
+            ptr::addr_of_mut!(SLOT).cast::<[u8; SLOT_LEN]>(),
+            record.store(),
+        )
+    };
+    cortex_m::peripheral::SCB::sys_reset()
+}
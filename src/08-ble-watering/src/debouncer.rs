@@ -1,37 +1,55 @@
-use embassy_nrf::gpio::{Input, Level};
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::{select, Either};
+use embassy_nrf::gpio::Input;
+use embassy_time::{Instant, Timer};
+use planty_core::debounce::{self, Edge, Strategy};
 
+/// Debounces a GPIO input with one of the `planty_core::debounce` strategies.
 pub struct Debouncer<'a> {
     input: Input<'a>,
-    debounce: Duration,
+    debouncer: debounce::Debouncer,
 }
 
 impl<'a> Debouncer<'a> {
-    pub fn new(input: Input<'a>, debounce: Duration) -> Self {
-        Self { input, debounce }
+    pub fn new(input: Input<'a>, strategy: Strategy) -> Self {
+        let debouncer = debounce::Debouncer::new(strategy, input.is_high());
+        Self { input, debouncer }
     }
 
-    /// Debounces the input signal by waiting for a stable level.
+    /// Waits for the next debounced level change.
     ///
-    /// This method continuously checks the input level and waits for any edge.
-    /// After detecting an edge, it waits for the specified debounce duration
-    /// and checks the input level again. If the level has changed, it returns
-    /// the new level.
+    /// The input is watched with GPIOTE level sensing against the last level
+    /// seen, so the CPU sleeps between changes and a change that happens
+    /// before the wait starts is still noticed. Bounce that ends at the
+    /// original level is handled by the strategy instead of being lost.
     ///
     /// # Returns
     ///
-    /// * `Level` - The stable level of the input signal after debouncing.
-    pub async fn debounce(&mut self) -> Level {
+    /// * `Edge` - The new level, and when the input first left the old one.
+    pub async fn next(&mut self) -> Edge {
         loop {
-            let l1 = self.input.get_level();
+            let raw = self.debouncer.raw();
+            let input = &mut self.input;
+            let change = async move {
+                if raw {
+                    input.wait_for_low().await
+                } else {
+                    input.wait_for_high().await
+                }
+            };
+            let deadline = self.debouncer.deadline();
+            let deadline = async move {
+                match deadline {
+                    Some(at) => Timer::at(Instant::from_millis(at)).await,
+                    None => core::future::pending().await,
+                }
+            };
 
-            self.input.wait_for_any_edge().await;
-
-            Timer::after(self.debounce).await;
-
-            let l2 = self.input.get_level();
-            if l1 != l2 {
-                break l2;
+            let edge = match select(change, deadline).await {
+                Either::First(()) => self.debouncer.input(!raw, Instant::now().as_millis()),
+                Either::Second(()) => self.debouncer.poll(Instant::now().as_millis()),
+            };
+            if let Some(edge) = edge {
+                return edge;
             }
         }
     }
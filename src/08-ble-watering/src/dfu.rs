@@ -0,0 +1,262 @@
+//! Firmware updates over BLE.
+//!
+//! A client uploads a new application image into the DFU partition through
+//! the DFU service, one chunk at a time, and waits for the status
+//! notification after each chunk before sending the next one:
+//!
+//! - control `0x01` start: image length (`u32`) followed by its signature
+//! - data: offset (`u32`) followed by up to `MAX_CHUNK_LEN` bytes of image
+//! - control `0x02` finish: verify the image and reboot into it
+//! - control `0x03` abort
+//!
+//! Status is `[state, error, next offset (u32)]`, all little endian.
+//!
+//! Images are signed with ed25519 over their SHA-512, and only an image whose
+//! signature matches `dfu_public.key` is handed to the bootloader, which
+//! checks it once more before marking it (embassy-boot's `ed25519-salty`). Use
+//! `planty-cli sign` to build a signed update package from an ELF or BIN.
+//! Writing and verifying the image is up to `planty_core::dfu`.
+//!
+//! The bootloader swaps the image in on the next boot. The new firmware has
+//! `HEALTH_CHECK_TIMEOUT` to prove itself healthy before it is marked as
+//! booted; otherwise we reset and the bootloader rolls back to the old one.
+
+use core::cell::Cell;
+
+use ed25519::{signature, signature::Verifier, Signature};
+use embassy_boot::{FirmwareUpdater, FirmwareUpdaterConfig, FirmwareUpdaterError, State};
+use embassy_sync::{
+    blocking_mutex::{
+        self,
+        raw::{NoopRawMutex, ThreadModeRawMutex},
+    },
+    channel::{Channel, TrySendError},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Vec;
+use nrf_softdevice::{Flash, Softdevice};
+use planty_core::dfu::{Bootloader, Error, ImageWriter, Phase};
+
+use crate::ble;
+
+pub use planty_core::dfu::{Status, MAX_CHUNK_LEN, SIGNATURE_LEN};
+
+/// Key that update images have to be signed with. The checked in key has no
+/// published secret: generate your own pair with `planty-cli keygen`.
+const PUBLIC_KEY: &[u8; 32] = include_bytes!("../dfu_public.key");
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Gives the final status notification a chance to go out before resetting.
+const RESET_DELAY: Duration = Duration::from_secs(1);
+
+const OP_START: u8 = 0x01;
+const OP_FINISH: u8 = 0x02;
+const OP_ABORT: u8 = 0x03;
+
+pub enum Request {
+    Start {
+        len: u32,
+        signature: [u8; SIGNATURE_LEN],
+    },
+    Chunk {
+        offset: u32,
+        data: Vec<u8, MAX_CHUNK_LEN>,
+    },
+    Finish,
+    Abort,
+}
+
+impl Request {
+    pub fn parse_control(bytes: &[u8]) -> Option<Self> {
+        match bytes.split_first()? {
+            (&OP_START, args) if args.len() == 4 + SIGNATURE_LEN => {
+                let (len, signature) = args.split_at(4);
+                Some(Request::Start {
+                    len: u32::from_le_bytes(len.try_into().ok()?),
+                    signature: signature.try_into().ok()?,
+                })
+            }
+            (&OP_FINISH, []) => Some(Request::Finish),
+            (&OP_ABORT, []) => Some(Request::Abort),
+            _ => None,
+        }
+    }
+
+    pub fn parse_data(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() <= 4 {
+            return None;
+        }
+        let (offset, data) = bytes.split_at(4);
+        Some(Request::Chunk {
+            offset: u32::from_le_bytes(offset.try_into().ok()?),
+            data: Vec::from_slice(data).ok()?,
+        })
+    }
+}
+
+/// The key the image is checked against, verified with `salty`.
+struct PublicKey(&'static [u8; 32]);
+
+impl Verifier<Signature> for PublicKey {
+    fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), signature::Error> {
+        salty::PublicKey::try_from(self.0)
+            .and_then(|key| key.verify(message, &salty::Signature::from(&signature.to_bytes())))
+            .map_err(|_| signature::Error::new())
+    }
+}
+
+/// Hands verified images to the bootloader through its state partition.
+/// embassy-boot only marks an image once its signature checks out.
+struct Updater<'d, DFU: NorFlash, STATE: NorFlash>(FirmwareUpdater<'d, DFU, STATE>);
+
+impl<DFU: NorFlash, STATE: NorFlash> Bootloader for Updater<'_, DFU, STATE> {
+    async fn mark_updated(
+        &mut self,
+        len: u32,
+        signature: &[u8; SIGNATURE_LEN],
+    ) -> Result<(), Error> {
+        self.0
+            .verify_and_mark_updated(PUBLIC_KEY, signature, len)
+            .await
+            .map_err(|error| match error {
+                FirmwareUpdaterError::Signature(_) => Error::BadSignature,
+                _ => Error::Flash,
+            })
+    }
+}
+
+static REQUESTS: Channel<ThreadModeRawMutex, Request, 2> = Channel::new();
+
+/// The writer's last status, so GATT callbacks can reject a write without
+/// misreporting where the upload stands.
+static STATUS: blocking_mutex::Mutex<ThreadModeRawMutex, Cell<Status>> =
+    blocking_mutex::Mutex::new(Cell::new(Status {
+        phase: Phase::Idle,
+        error: Error::None,
+        next_offset: 0,
+    }));
+
+/// Raised by the controller once the system has done useful work, which is
+/// what a freshly swapped in firmware has to do before it is kept.
+pub static HEALTH_CHECK: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+#[embassy_executor::task]
+pub async fn dfu_task(softdevice: &'static Softdevice) {
+    let flash = Mutex::<NoopRawMutex, _>::new(Flash::take(softdevice));
+    let config = FirmwareUpdaterConfig::from_linkerfile(&flash, &flash);
+    // The image writer gets a partition of its own over the same flash
+    let dfu = FirmwareUpdaterConfig::from_linkerfile(&flash, &flash).dfu;
+
+    let mut aligned = [0; 4];
+    let mut updater = Updater(FirmwareUpdater::new(config, &mut aligned));
+    confirm_boot(&mut updater.0).await;
+
+    let mut image = ImageWriter::new(dfu);
+    publish(image.status(Error::None));
+
+    loop {
+        let result = match REQUESTS.receive().await {
+            Request::Start { len, signature } => {
+                defmt::info!("DFU started, {} bytes", len);
+                image.start(len, signature).await
+            }
+            Request::Chunk { offset, data } => image.write(offset, &data).await,
+            Request::Finish => image.finish(&PublicKey(PUBLIC_KEY), &mut updater).await,
+            Request::Abort => {
+                defmt::info!("DFU aborted");
+                image.abort();
+                Ok(())
+            }
+        };
+
+        let error = result.err().unwrap_or(Error::None);
+        if error != Error::None {
+            defmt::warn!("DFU error: {:?}", defmt::Debug2Format(&error));
+        }
+        let status = image.status(error);
+        publish(status);
+
+        if status.phase == Phase::Verified {
+            defmt::info!("DFU image verified, rebooting into it");
+            Timer::after(RESET_DELAY).await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+}
+
+/// Handles a write to the control point from a GATT callback.
+pub fn control_written(bytes: &[u8]) {
+    match Request::parse_control(bytes) {
+        Some(request) => submit(request),
+        None => reject(Error::InvalidRequest, None),
+    }
+}
+
+/// Handles a write to the data characteristic from a GATT callback.
+pub fn data_written(bytes: &[u8]) {
+    match Request::parse_data(bytes) {
+        Some(request) => submit(request),
+        None => reject(Error::InvalidRequest, None),
+    }
+}
+
+/// Reports `Busy` to the client if the previous request has not been handled
+/// yet. The offset lets it resend the rejected chunk.
+fn submit(request: Request) {
+    let offset = match &request {
+        Request::Chunk { offset, .. } => Some(*offset),
+        _ => None,
+    };
+    if let Err(TrySendError::Full(_)) = REQUESTS.try_send(request) {
+        reject(Error::Busy, offset);
+    }
+}
+
+/// Reports `error` along with the writer's phase, and `next_offset` if given
+/// rather than the writer's.
+fn reject(error: Error, next_offset: Option<u32>) {
+    let status = STATUS.lock(Cell::get);
+    ble::publish(ble::Update::DfuStatus(Status {
+        error,
+        next_offset: next_offset.unwrap_or(status.next_offset),
+        ..status
+    }));
+}
+
+/// Reports the writer's status.
+fn publish(status: Status) {
+    STATUS.lock(|cell| cell.set(status));
+    ble::publish(ble::Update::DfuStatus(status));
+}
+
+/// Keeps a freshly swapped in firmware if it passes the health check within
+/// `HEALTH_CHECK_TIMEOUT`, otherwise resets so the bootloader rolls back.
+async fn confirm_boot<DFU: NorFlash, STATE: NorFlash>(
+    updater: &mut FirmwareUpdater<'_, DFU, STATE>,
+) {
+    match updater.get_state().await {
+        Ok(State::Swap) => {}
+        Ok(_) => return,
+        Err(_) => {
+            defmt::warn!("Failed to read bootloader state");
+            return;
+        }
+    }
+
+    defmt::info!("Running updated firmware, waiting for health check");
+    if with_timeout(HEALTH_CHECK_TIMEOUT, HEALTH_CHECK.wait())
+        .await
+        .is_err()
+    {
+        defmt::error!("Health check timed out, rolling back");
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
+    match updater.mark_booted().await {
+        Ok(()) => defmt::info!("Updated firmware marked as booted"),
+        Err(_) => defmt::warn!("Failed to mark firmware as booted"),
+    }
+}
@@ -0,0 +1,103 @@
+//! Drives the LED matrix from the controller status. What to show is decided
+//! by `planty_core::display::render`; this module only keeps the status and
+//! refreshes the matrix.
+//!
+//! The matrix is multiplexed in software and keeps the CPU awake, so it goes
+//! dark `AWAKE_TIME` after the last `wake` unless
+//! `planty_core::display::Status::needs_attention`.
+
+use core::{cell::Cell, fmt::Write as _};
+
+use embassy_futures::select::{select, Either};
+use embassy_nrf::gpio::Output;
+use embassy_sync::{
+    blocking_mutex::{raw::ThreadModeRawMutex, Mutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant};
+use microbit_bsp::display::{Frame, LedMatrix};
+use planty_core::display::{self, Status};
+
+use crate::ble;
+
+pub type Matrix = LedMatrix<Output<'static>, 5, 5>;
+
+/// How long each animation step is shown.
+const FRAME_TIME: Duration = Duration::from_millis(200);
+const AWAKE_TIME: Duration = Duration::from_secs(30);
+
+static STATUS: Mutex<ThreadModeRawMutex, Cell<Status>> = Mutex::new(Cell::new(Status::new()));
+static CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+static WAKE: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Changes the status shown on the matrix.
+pub fn update(f: impl FnOnce(&mut Status)) {
+    STATUS.lock(|status| {
+        let mut next = status.get();
+        f(&mut next);
+        status.set(next);
+    });
+    CHANGED.signal(());
+}
+
+/// Turns the display on and scrolls the last reading.
+pub fn wake() {
+    WAKE.signal(());
+}
+
+fn status() -> Status {
+    let mut status = STATUS.lock(Cell::get);
+    status.connected = ble::connection_count() > 0;
+    status
+}
+
+fn to_frame(rows: display::Frame) -> Frame<5, 5> {
+    let mut frame = Frame::empty();
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..5 {
+            if row & (0b10000 >> x) != 0 {
+                frame.set(x, y);
+            }
+        }
+    }
+    frame
+}
+
+/// Shows the status on the matrix, and scrolls the last reading on `wake`.
+#[embassy_executor::task]
+pub async fn display_task(mut matrix: Matrix) {
+    let mut awake_until = Instant::now() + AWAKE_TIME;
+    let mut tick: u32 = 0;
+
+    loop {
+        let status = status();
+        if Instant::now() >= awake_until && !status.needs_attention() {
+            matrix.clear();
+            matrix.render();
+            if let Either::Second(()) = select(CHANGED.wait(), WAKE.wait()).await {
+                awake_until = Instant::now() + AWAKE_TIME;
+                scroll_reading(&mut matrix, &status).await;
+            }
+            continue;
+        }
+
+        let frame = to_frame(display::render(&status, tick));
+        tick = tick.wrapping_add(1);
+        match select(matrix.display(frame, FRAME_TIME), WAKE.wait()).await {
+            Either::First(()) => {}
+            Either::Second(()) => {
+                awake_until = Instant::now() + AWAKE_TIME;
+                scroll_reading(&mut matrix, &status).await;
+            }
+        }
+    }
+}
+
+async fn scroll_reading(matrix: &mut Matrix, status: &Status) {
+    if let Some(reading) = status.moisture {
+        let mut text = heapless::String::<8>::new();
+        // Five digits at most, always fits
+        let _ = write!(text, "{}", reading);
+        matrix.scroll(&text).await;
+    }
+}
@@ -0,0 +1,33 @@
+//! The queue feeding `control_task`, a `planty_core::tasks::Events`.
+
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use planty_core::{
+    bus::{Counters, Push},
+    control::Event,
+    tasks::Events,
+};
+
+pub use planty_core::{bus::Priority, tasks::Source};
+
+/// Pending events per priority.
+pub const CAPACITY: usize = 4;
+
+pub static EVENTS: Events<ThreadModeRawMutex, CAPACITY> = Events::new();
+
+/// Queues an event. Returns false if it was dropped because too many events
+/// of that priority are pending.
+pub fn send(priority: Priority, source: Source, event: Event) -> bool {
+    if EVENTS.send(priority, source, event) == Push::Dropped {
+        defmt::warn!(
+            "Event queue full, dropping {:?} event {:?}",
+            defmt::Debug2Format(&priority),
+            defmt::Debug2Format(&event)
+        );
+        return false;
+    }
+    true
+}
+
+pub fn counters() -> Counters {
+    EVENTS.counters()
+}
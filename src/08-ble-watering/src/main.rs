@@ -1,226 +1,292 @@
 #![no_std]
 #![no_main]
 
-extern crate alloc;
-use core::cell::RefCell;
-
-use ble::{softdevice_task, PlantServiceEvent, Server, ServerEvent, ADV_DATA, SCAN_DATA};
+use ble::{beacon_task, ble_task, softdevice_config, softdevice_task, update_task};
 use defmt::unwrap;
+use defmt_rtt as _;
 use embassy_executor::Spawner;
 use embassy_nrf::{
     bind_interrupts,
-    gpio::{Input, Level, Output, OutputDrive, Pin as _, Pull},
-    saadc::{self, ChannelConfig, Config, Saadc},
-};
-use embassy_sync::{
-    blocking_mutex::raw::{CriticalSectionRawMutex, ThreadModeRawMutex},
-    channel::Channel,
-    mutex::Mutex,
-    signal::Signal,
+    gpio::{AnyPin, Input, Level, Output, OutputDrive, Pin as _, Pull},
+    pwm::SimplePwm,
+    saadc,
 };
-use embassy_time::Duration;
-use nrf_softdevice::{
-    ble::{gatt_server, peripheral, Connection},
-    Softdevice,
+use events::Source;
+use nrf_softdevice::Softdevice;
+use planty_core::{
+    alerts::Alert,
+    battery, beacon,
+    control::{Rejection, State, Transition},
+    debounce::Strategy,
+    gestures::Button,
+    tasks::{self, Measurement, Message, Plant, Sample},
+    temperature::Compensation,
 };
-use {defmt_rtt as _, panic_probe as _};
+use watchdog::Task;
 
+mod alerts;
 mod ble;
+mod buttons;
+mod connections;
+mod crash;
 mod debouncer;
+mod dfu;
+mod display;
+mod events;
+mod power;
+mod reset;
+mod sensor;
+mod watchdog;
 
 bind_interrupts!(struct Irqs {
     SAADC => saadc::InterruptHandler;
 });
 
-const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(10);
-const WATERING_DURATION: Duration = Duration::from_secs(5);
-const DEFAULT_THRESHOLD: u16 = 2000;
-const THRESHOLD_BUFFER: u16 = 100;
+#[embassy_executor::task]
+async fn measurement_task() {
+    tasks::measure(
+        &events::EVENTS,
+        || power::mode().measurement_interval(),
+        || watchdog::heartbeat(Task::Measurement),
+    )
+    .await
+}
 
-enum ConnectionState {
-    Connected(Connection),
-    Disconnected,
+/// What `control_task` drives: the pump, the sensor and everything a
+/// measurement is reported to.
+struct Hardware {
+    pump_control: Output<'static>,
+    sensor: sensor::Sensor,
+    /// Last published, so it is only sent again when it changes.
+    estimate: Option<Compensation>,
+    /// Last advertised, so the pump can flip `FLAG_WATERING` between
+    /// measurements.
+    beacon: beacon::Status,
 }
 
-static SERVER: Mutex<CriticalSectionRawMutex, RefCell<Option<Server>>> =
-    Mutex::new(RefCell::new(None));
+impl Plant for Hardware {
+    const RATIOMETRIC: bool = cfg!(feature = "ratiometric");
 
-static CONNECTION_STATE: Signal<ThreadModeRawMutex, ConnectionState> = Signal::new();
+    async fn sample(&mut self) -> Sample {
+        self.sensor.sample().await
+    }
 
-#[derive(Debug, PartialEq)]
-enum Event {
-    Water,
-    WateringComplete,
-    Measure,
-    Calibrate,
-}
+    fn set_pump(&mut self, on: bool) {
+        if on {
+            defmt::info!("Pump on");
+            self.pump_control.set_high();
+        } else {
+            defmt::info!("Pump off");
+            self.pump_control.set_low();
+        }
+        display::update(|status| {
+            status.watering = on;
+            if on {
+                status.faults.pump_timeout = false;
+            }
+        });
 
-#[derive(Debug, PartialEq)]
-enum SystemState {
-    Watering,
-    Idle,
-}
+        if on {
+            self.beacon.flags |= beacon::FLAG_WATERING;
+        } else {
+            self.beacon.flags &= !beacon::FLAG_WATERING;
+        }
+        ble::publish(ble::Update::Advertisement(self.beacon));
+    }
+
+    fn measured(&mut self, measurement: &Measurement) {
+        let reading = measurement.sample.moisture;
+        let vdd = measurement.sample.vdd_millivolts;
+        defmt::info!("Moisture reading: {}, supply: {} mV", reading, vdd);
+        power::update(vdd);
+        if let Some(quarter_celsius) = measurement.sample.quarter_celsius {
+            defmt::info!("Temperature: {} C", quarter_celsius as f32 / 4.0);
+            ble::publish(ble::Update::Temperature(quarter_celsius));
+        }
+        if measurement.estimate != self.estimate {
+            self.estimate = measurement.estimate;
+            if let Some(estimate) = measurement.estimate {
+                defmt::info!(
+                    "Estimated temperature compensation: {} centicounts per degree C",
+                    estimate.centicounts_per_celsius
+                );
+            }
+            ble::publish(ble::Update::CompensationEstimate(measurement.estimate));
+        }
+
+        ble::publish(ble::Update::Moisture(reading));
+        ble::publish(ble::Update::SupplyVoltage(vdd));
+        dfu::HEALTH_CHECK.signal(());
+        ble::publish(ble::Update::EventCounters(events::counters()));
+
+        if measurement.health_changed {
+            // Only on changes, the update queue is already busy with every
+            // measurement
+            ble::publish(ble::Update::SensorHealth(measurement.health));
+            if !measurement.health.is_ok() {
+                alerts::play(Alert::Fault);
+            }
+        }
+        if !measurement.health.is_ok() {
+            defmt::warn!(
+                "Moisture probe fault: {:?}, not watering",
+                defmt::Debug2Format(&measurement.health)
+            );
+        }
+        if measurement.low_battery {
+            defmt::warn!("Battery low ({} mV), replace the batteries", vdd);
+        }
+        if measurement.battery_went_low {
+            alerts::play(Alert::Fault);
+        }
+        display::update(|status| {
+            status.moisture = Some(reading);
+            status.faults.low_battery = measurement.low_battery;
+            status.faults.probe = !measurement.health.is_ok();
+        });
+
+        if measurement.dry && measurement.watering_ms.is_none() {
+            defmt::warn!("Soil is dry but the supply is too low to run the pump");
+            alerts::play(Alert::NeedsWater);
+        }
 
-static CHANNEL: Channel<ThreadModeRawMutex, Event, 4> = Channel::new();
-static MOISTURE_SIGNAL: Signal<ThreadModeRawMutex, u16> = Signal::new();
+        // Whether the pump runs is up to `set_pump`
+        let mut flags = self.beacon.flags & beacon::FLAG_WATERING;
+        if measurement.low_battery {
+            flags |= beacon::FLAG_LOW_BATTERY;
+        }
+        if !measurement.health.is_ok() {
+            flags |= beacon::FLAG_PROBE_FAULT;
+        }
+        self.beacon = beacon::Status {
+            moisture: reading,
+            threshold: measurement.threshold,
+            flags,
+            battery_percent: Some(battery::percent(vdd)),
+        };
+        ble::publish(ble::Update::Advertisement(self.beacon));
 
-#[embassy_executor::task]
-async fn button_task(mut button: debouncer::Debouncer<'static>) {
-    let sender = CHANNEL.sender();
-    loop {
-        button.debounce().await;
-        sender.send(Event::Water).await;
-        button.debounce().await;
-        sender.send(Event::WateringComplete).await;
+        if let Some(ms) = measurement.watering_ms {
+            defmt::info!("Soil is dry, watering for {} ms", ms);
+        }
     }
-}
 
-#[embassy_executor::task]
-async fn measurement_task() {
-    let sender = CHANNEL.sender();
-    loop {
-        embassy_time::Timer::after(MEASUREMENT_INTERVAL).await;
-        sender.send(Event::Measure).await;
+    fn pump_timed_out(&mut self) {
+        defmt::warn!("Pump ran for the maximum without being stopped");
+        display::update(|status| status.faults.pump_timeout = true);
+        alerts::play(Alert::Fault);
     }
-}
 
-#[embassy_executor::task]
-async fn ble_task(softdevice: &'static Softdevice) {
-    let config = peripheral::Config::default();
-    let sender = CHANNEL.sender();
-
-    loop {
-        let connection = match peripheral::advertise_connectable(
-            softdevice,
-            peripheral::ConnectableAdvertisement::ScannableUndirected {
-                adv_data: &ADV_DATA,
-                scan_data: &SCAN_DATA,
-            },
-            &config,
-        )
-        .await
-        {
-            Ok(connection) => connection,
-            Err(error) => {
-                defmt::warn!("Advertisement error: {:?}", error);
-                continue;
-            }
-        };
+    fn compensation(&mut self) -> Compensation {
+        sensor::compensation()
+    }
+
+    fn calibrated(&mut self, reading: u16, threshold: u16) {
+        defmt::info!("Calibrated at {}, new threshold: {}", reading, threshold);
+        alerts::play(Alert::CalibrationCaptured);
+    }
 
-        defmt::info!("Connection established");
-        CONNECTION_STATE.signal(ConnectionState::Connected(connection.clone()));
-        let server_guard = SERVER.lock().await;
-        let server_ref = server_guard.borrow();
-
-        if let Some(ref server) = server_ref.as_ref() {
-            let _disconnected = gatt_server::run(&connection, *server, |event| match event {
-                ServerEvent::PlantService(evt) => match evt {
-                    PlantServiceEvent::PumpControlWrite(value) => {
-                        if value > 0 {
-                            sender.send(Event::Water);
-                        } else {
-                            sender.send(Event::WateringComplete);
-                        }
-                    }
-                    PlantServiceEvent::MoistureLevelCccdWrite { notifications: _ } => {}
-                },
-            })
-            .await;
+    fn reply(
+        &mut self,
+        (event, source): Message,
+        state: State,
+        result: Result<Transition, Rejection>,
+    ) {
+        match source {
+            Source::Ble(request) => {
+                ble::publish(ble::Update::PumpStatus(request.ack(result.into())));
+            }
+            Source::Button if result.is_err() => alerts::play(Alert::Rejected),
+            Source::Button | Source::Timer => {}
         }
+        if let Err(rejection) = result {
+            defmt::info!(
+                "{:?} rejected while {:?}: {:?}",
+                defmt::Debug2Format(&event),
+                defmt::Debug2Format(&state),
+                defmt::Debug2Format(&rejection)
+            );
+        }
+    }
 
-        CONNECTION_STATE.signal(ConnectionState::Disconnected);
-        defmt::info!("Disconnected");
+    fn heartbeat(&mut self) {
+        watchdog::heartbeat(Task::Control);
     }
 }
 
+/// Runs `planty_core::control::TABLE` through `planty_core::tasks::control`.
 #[embassy_executor::task]
-async fn control_task(mut pump_control: Output<'static>, mut saadc: Saadc<'static, 1>) {
-    let receiver = CHANNEL.receiver();
-    let moisture_threshold = DEFAULT_THRESHOLD;
-    let mut system_state = SystemState::Idle;
-
-    loop {
-        let event = receiver.receive().await;
-
-        system_state = match (system_state, event) {
-            (SystemState::Idle, Event::Water) => {
-                defmt::info!("Watering requested");
-                pump_control.set_high();
-                SystemState::Watering
-            }
-            (SystemState::Watering, Event::WateringComplete) => {
-                defmt::info!("Watering complete");
-                pump_control.set_low();
-                SystemState::Idle
-            }
-            (SystemState::Idle, Event::Measure) => {
-                let reading = read_moisture(&mut saadc).await;
-                defmt::info!("Moisture reading: {}", reading);
-
-                // Update BLE characteristic if connected
-                if let ConnectionState::Connected(ref connection) = CONNECTION_STATE.wait().await {
-                    let server_guard = SERVER.lock().await;
-                    let mut server_ref = server_guard.borrow_mut();
-
-                    if let Some(ref mut server) = server_ref.as_mut() {
-                        server
-                            .plant_service
-                            .moisture_level_notify(connection, &reading)
-                            .unwrap();
-                    }
-                }
-                MOISTURE_SIGNAL.signal(reading);
-
-                if reading > moisture_threshold {
-                    defmt::info!("Soil is dry, watering");
-                    pump_control.set_high();
-                    embassy_time::Timer::after(WATERING_DURATION).await;
-                    pump_control.set_low();
-                    defmt::info!("Automatic watering complete");
-                }
-                SystemState::Idle
-            }
-            (current_state, _) => current_state,
-        };
-    }
+async fn control_task(pump_control: Output<'static>, sensor: sensor::Sensor) {
+    let hardware = Hardware {
+        pump_control,
+        sensor,
+        estimate: None,
+        beacon: beacon::Status::default(),
+    };
+    tasks::control(&events::EVENTS, hardware).await
 }
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     let p = embassy_nrf::init(Default::default());
 
-    // Initialize softdevice
-    let softdevice_config = nrf_softdevice::Config::default();
-    let softdevice = Softdevice::enable(&softdevice_config);
+    // Drive the pump low before anything else can fail
+    let pump_control = Output::new(p.P0_03.degrade(), Level::Low, OutputDrive::Standard);
+    let watchdog = watchdog::start(p.WDT);
+    let reset_reason = reset::take_reason();
+    crash::report();
 
-    // set global SERVER
-    SERVER
-        .lock()
-        .await
-        .replace(Some(unwrap!(Server::new(softdevice))));
+    // Initialize softdevice
+    let softdevice = Softdevice::enable(&softdevice_config());
+    let server = unwrap!(ble::init_server(softdevice));
 
     // Initialize hardware
-    let button = Input::new(p.P0_14.degrade(), Pull::Up);
-    let button = debouncer::Debouncer::new(button, Duration::from_millis(20));
-
-    let pump_control = Output::new(p.P0_03.degrade(), Level::Low, OutputDrive::Standard);
-
-    // Setup SAADC
-    let mut config = Config::default();
-    config.resolution = saadc::Resolution::_12BIT;
-    let channel_config = ChannelConfig::single_ended(p.P0_04);
-    let saadc = Saadc::new(p.SAADC, Irqs, config, [channel_config]);
+    let button =
+        |pin: AnyPin, strategy| debouncer::Debouncer::new(Input::new(pin, Pull::Up), strategy);
+    // A starts the pump, so it has to be really pressed; B only wakes the
+    // display and calibrates, so it reacts right away
+    let button_a = button(p.P0_14.degrade(), Strategy::Stable { stable_ms: 20 });
+    let button_b = button(p.P0_23.degrade(), Strategy::Lockout { lockout_ms: 30 });
+
+    let sensor = sensor::Sensor::new(p.SAADC, p.P0_04, softdevice);
+
+    let output = |pin: AnyPin| Output::new(pin, Level::Low, OutputDrive::Standard);
+    let matrix = display::Matrix::new(
+        [
+            output(p.P0_21.degrade()),
+            output(p.P0_22.degrade()),
+            output(p.P0_15.degrade()),
+            output(p.P0_24.degrade()),
+            output(p.P0_19.degrade()),
+        ],
+        [
+            output(p.P0_28.degrade()),
+            output(p.P0_11.degrade()),
+            output(p.P0_31.degrade()),
+            output(p.P1_05.degrade()),
+            output(p.P0_30.degrade()),
+        ],
+    );
+
+    let speaker = alerts::Speaker::new(SimplePwm::new_1ch(p.PWM0, p.P0_00));
 
     // Spawn tasks
+    unwrap!(spawner.spawn(watchdog::watchdog_task(watchdog)));
     unwrap!(spawner.spawn(softdevice_task(softdevice)));
-    unwrap!(spawner.spawn(ble_task(softdevice)));
-    unwrap!(spawner.spawn(button_task(button)));
+    unwrap!(spawner.spawn(update_task(server)));
+    ble::publish(ble::Update::ResetReason(reset_reason));
+    unwrap!(spawner.spawn(dfu::dfu_task(softdevice)));
+    // Broadcast the plant status without ever accepting a connection
+    if cfg!(feature = "beacon") {
+        unwrap!(spawner.spawn(beacon_task(softdevice)));
+    } else {
+        unwrap!(spawner.spawn(ble_task(spawner, softdevice, server)));
+    }
+    unwrap!(spawner.spawn(buttons::edge_task(Button::A, button_a)));
+    unwrap!(spawner.spawn(buttons::edge_task(Button::B, button_b)));
+    unwrap!(spawner.spawn(buttons::gesture_task()));
+    unwrap!(spawner.spawn(display::display_task(matrix)));
+    unwrap!(spawner.spawn(alerts::alert_task(speaker)));
     unwrap!(spawner.spawn(measurement_task()));
-    unwrap!(spawner.spawn(control_task(pump_control, saadc)));
-}
-
-async fn read_moisture(adc: &mut Saadc<'_, 1>) -> u16 {
-    let mut buf = [0i16; 1];
-    adc.sample(&mut buf).await;
-    buf[0] as u16
+    unwrap!(spawner.spawn(control_task(pump_control, sensor)));
 }
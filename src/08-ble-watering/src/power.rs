@@ -0,0 +1,91 @@
+//! Battery aware power management.
+//!
+//! The supply voltage measured with every sample picks a power mode, and the
+//! mode stretches the measurement and advertising intervals as the battery
+//! drains. See `docs/power.md` for the current budget of each mode and
+//! `planty-cli energy` for the estimator behind it; keep the timings here and
+//! in `tools/planty-cli/src/energy.rs` in step.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use embassy_time::Duration;
+
+/// Keeps the mode from flapping when the voltage sits right at a threshold.
+const HYSTERESIS_MILLIVOLTS: u16 = 100;
+
+/// Ordered from the most to the least power hungry.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum PowerMode {
+    /// Fresh batteries or USB power.
+    Normal,
+    /// Batteries are getting low, save what is left for watering.
+    Saver,
+    /// Nearly flat, only check in occasionally.
+    Critical,
+}
+
+impl PowerMode {
+    /// Lowest supply voltage at which the mode is entered.
+    const fn min_millivolts(self) -> u16 {
+        match self {
+            PowerMode::Normal => 2700,
+            PowerMode::Saver => 2400,
+            PowerMode::Critical => 0,
+        }
+    }
+
+    pub const fn measurement_interval(self) -> Duration {
+        match self {
+            PowerMode::Normal => Duration::from_secs(10),
+            PowerMode::Saver => Duration::from_secs(60),
+            PowerMode::Critical => Duration::from_secs(300),
+        }
+    }
+
+    /// Advertising interval in units of 0.625 ms.
+    pub const fn advertising_interval(self) -> u32 {
+        match self {
+            PowerMode::Normal => 160,    // 100 ms
+            PowerMode::Saver => 800,     // 500 ms
+            PowerMode::Critical => 3200, // 2 s
+        }
+    }
+
+    /// The mode for a new supply voltage reading, given the current mode.
+    /// Moving to a less frugal mode takes `HYSTERESIS_MILLIVOLTS` extra.
+    pub fn next(self, vdd_millivolts: u16) -> Self {
+        [PowerMode::Normal, PowerMode::Saver, PowerMode::Critical]
+            .into_iter()
+            .find(|&mode| {
+                let hysteresis = if mode < self {
+                    HYSTERESIS_MILLIVOLTS
+                } else {
+                    0
+                };
+                vdd_millivolts >= mode.min_millivolts() + hysteresis
+            })
+            .unwrap_or(PowerMode::Critical)
+    }
+}
+
+static MODE: Mutex<ThreadModeRawMutex, Cell<PowerMode>> = Mutex::new(Cell::new(PowerMode::Normal));
+
+pub fn mode() -> PowerMode {
+    MODE.lock(Cell::get)
+}
+
+/// Feeds a supply voltage reading into the power mode.
+pub fn update(vdd_millivolts: u16) {
+    let current = mode();
+    let next = current.next(vdd_millivolts);
+    if next != current {
+        defmt::info!(
+            "Power mode {:?} -> {:?} at {} mV",
+            current,
+            next,
+            vdd_millivolts
+        );
+        MODE.lock(|mode| mode.set(next));
+    }
+}
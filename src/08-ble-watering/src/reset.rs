@@ -0,0 +1,40 @@
+//! Why the chip last reset, read from the POWER peripheral's RESETREAS.
+
+use embassy_nrf::pac;
+
+/// RESETREAS bits, in the order they are reported.
+const REASONS: [(u32, &str); 9] = [
+    (1 << 0, "reset pin"),
+    (1 << 1, "watchdog"),
+    (1 << 2, "soft reset"),
+    (1 << 3, "CPU lockup"),
+    (1 << 16, "wake from System OFF (GPIO)"),
+    (1 << 17, "wake from System OFF (LPCOMP)"),
+    (1 << 18, "debug interface"),
+    (1 << 19, "wake from System OFF (NFC)"),
+    (1 << 20, "wake from System OFF (VBUS)"),
+];
+
+/// Reads and clears RESETREAS. The bits accumulate until cleared, so this
+/// must run once per boot, and before the softdevice takes over POWER.
+/// Zero means power-on or brownout.
+pub fn take_reason() -> u32 {
+    let power = unsafe { &*pac::POWER::ptr() };
+    let reason = power.resetreas.read().bits();
+    power.resetreas.write(|w| unsafe { w.bits(reason) });
+
+    match REASONS.iter().find(|(bit, _)| reason & bit != 0) {
+        Some((_, name)) => defmt::info!("Reset reason: {} ({:#x})", name, reason),
+        None => defmt::info!("Reset reason: power-on or brownout"),
+    }
+    reason
+}
+
+/// Forgets everything the board learned and restarts. Settings only live in
+/// RAM, so restarting restores the defaults; the crash record is the one
+/// thing that survives a reset and has to be cleared.
+pub fn factory_reset() -> ! {
+    defmt::warn!("Factory reset");
+    crate::crash::clear();
+    cortex_m::peripheral::SCB::sys_reset()
+}
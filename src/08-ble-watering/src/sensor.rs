@@ -0,0 +1,97 @@
+use core::cell::Cell;
+
+use embassy_nrf::{
+    peripherals::{P0_04, SAADC},
+    saadc::{self, ChannelConfig, Config, Saadc, VddInput},
+};
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use embassy_time::Instant;
+use nrf_softdevice::Softdevice;
+use planty_core::{
+    saadc::{to_millivolts, OffsetCalibration},
+    tasks::Sample,
+    temperature::Compensation,
+};
+
+use crate::Irqs;
+
+/// Set over BLE; none until then.
+static COMPENSATION: Mutex<ThreadModeRawMutex, Cell<Compensation>> =
+    Mutex::new(Cell::new(Compensation::NONE));
+
+pub fn compensation() -> Compensation {
+    COMPENSATION.lock(Cell::get)
+}
+
+pub fn set_compensation(compensation: Compensation) {
+    COMPENSATION.lock(|cell| cell.set(compensation));
+}
+
+/// The moisture probe and supply voltage, sampled through the SAADC. This
+/// particular probe reads ~2840 when very dry (in air or dry soil) and ~1180
+/// when very wet (submerged in water). The SAADC is only enabled for the
+/// duration of a sample, so it draws nothing between measurements.
+///
+/// The die temperature is read along with every sample, for the offset
+/// calibration and the temperature compensation. The SoftDevice reads it for
+/// us: it owns the TEMP peripheral while enabled, so embassy-nrf's driver
+/// can't be used. The offset is calibrated before the first sample and again
+/// whenever the temperature drifts, or on a timer while it can't be read.
+pub struct Sensor {
+    saadc: SAADC,
+    probe: P0_04,
+    softdevice: &'static Softdevice,
+    calibration: OffsetCalibration,
+}
+
+impl Sensor {
+    pub fn new(saadc: SAADC, probe: P0_04, softdevice: &'static Softdevice) -> Self {
+        Self {
+            saadc,
+            probe,
+            softdevice,
+            calibration: OffsetCalibration::new(),
+        }
+    }
+
+    pub async fn sample(&mut self) -> Sample {
+        let mut config = Config::default();
+        config.resolution = saadc::Resolution::_12BIT;
+        let channels = [
+            ChannelConfig::single_ended(&mut self.probe),
+            ChannelConfig::single_ended(VddInput),
+        ];
+
+        // Dropping the driver disables the SAADC again. The calibrated
+        // offset is kept while it is disabled.
+        let mut adc = Saadc::new(&mut self.saadc, Irqs, config, channels);
+        let quarter_celsius = match nrf_softdevice::temperature_celsius(self.softdevice) {
+            Ok(temperature) => Some(temperature.to_bits()),
+            Err(error) => {
+                defmt::warn!(
+                    "Failed to read die temperature, calibrating SAADC offset on a timer: {:?}",
+                    error
+                );
+                None
+            }
+        };
+        if self
+            .calibration
+            .due(Instant::now().as_millis(), quarter_celsius)
+        {
+            match quarter_celsius {
+                Some(t) => defmt::info!("Calibrating SAADC offset at {} degrees C", t / 4),
+                None => defmt::info!("Calibrating SAADC offset"),
+            }
+            adc.calibrate().await;
+        }
+        let mut buf = [0i16; 2];
+        adc.sample(&mut buf).await;
+
+        Sample {
+            moisture: buf[0].max(0) as u16,
+            vdd_millivolts: to_millivolts(buf[1]),
+            quarter_celsius,
+        }
+    }
+}
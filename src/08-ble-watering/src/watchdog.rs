@@ -0,0 +1,104 @@
+//! Hardware watchdog fed only while every long running task is alive.
+//!
+//! Each task checks in with `heartbeat` and `watchdog_task` only feeds the
+//! WDT once all of them have checked in since the last feed. A wedged task,
+//! most importantly `control_task` with the pump running, therefore ends in a
+//! watchdog reset, and the bootloader drives the pump low again.
+//!
+//! Tasks that sit waiting on purpose (for a button press, the next
+//! measurement, a connection) wrap that wait in `idle`, which keeps checking
+//! in. Any other await that never finishes stops the heartbeat.
+
+use core::{cell::Cell, future::Future};
+
+use embassy_nrf::{
+    peripherals::WDT,
+    wdt::{self, HaltConfig, SleepConfig, Watchdog, WatchdogHandle},
+};
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use embassy_time::{Duration, Timer};
+use planty_core::tasks;
+
+/// Must match the bootloader, which starts the watchdog before we do and
+/// whose configuration can't be changed afterwards.
+const TIMEOUT_TICKS: u32 = 32_768 * 30;
+/// How often the watchdog is fed, if every task checked in.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, defmt::Format)]
+pub enum Task {
+    Control,
+    /// `ble_task`, or `beacon_task` in beacon mode.
+    Ble,
+    Measurement,
+    /// `edge_task` for button A.
+    ButtonA,
+    /// `edge_task` for button B.
+    ButtonB,
+    Gestures,
+}
+
+const TASKS: [Task; 6] = [
+    Task::Control,
+    Task::Ble,
+    Task::Measurement,
+    Task::ButtonA,
+    Task::ButtonB,
+    Task::Gestures,
+];
+
+impl Task {
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+const ALL_ALIVE: u8 = (1 << TASKS.len()) - 1;
+
+/// Tasks that checked in since the watchdog was last fed.
+static ALIVE: Mutex<ThreadModeRawMutex, Cell<u8>> = Mutex::new(Cell::new(0));
+
+pub fn heartbeat(task: Task) {
+    ALIVE.lock(|alive| alive.set(alive.get() | task.bit()));
+}
+
+/// Waits for `future`, checking in for `task` while it is pending.
+pub async fn idle<F: Future>(task: Task, future: F) -> F::Output {
+    tasks::idle(|| heartbeat(task), future).await
+}
+
+/// Starts the watchdog, or takes over the one the bootloader started.
+pub fn start(wdt: WDT) -> WatchdogHandle {
+    let mut config = wdt::Config::default();
+    config.timeout_ticks = TIMEOUT_TICKS;
+    config.action_during_sleep = SleepConfig::RUN;
+    config.action_during_debug_halt = HaltConfig::PAUSE;
+
+    match Watchdog::try_new(wdt, config) {
+        Ok((_watchdog, [handle])) => handle,
+        Err(_) => {
+            defmt::info!("Watchdog already started by the bootloader");
+            // The bootloader configures a single reload register
+            unsafe { WatchdogHandle::steal(0) }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn watchdog_task(mut handle: WatchdogHandle) {
+    loop {
+        Timer::after(CHECK_INTERVAL).await;
+
+        let alive = ALIVE.lock(|alive| alive.replace(0));
+        if alive == ALL_ALIVE {
+            handle.pet();
+            continue;
+        }
+
+        for task in TASKS {
+            if alive & task.bit() == 0 {
+                defmt::warn!("{:?} missed its heartbeat", task);
+            }
+        }
+    }
+}
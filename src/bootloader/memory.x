@@ -0,0 +1,34 @@
+MEMORY
+{
+  /* NOTE 1 K = 1 KiBi = 1024 bytes */
+  /* nRF52833 with Softdevice S140 7.3.0, must match 08-ble-watering/memory.x */
+  MBR_SOFTDEVICE                    : ORIGIN = 0x00000000, LENGTH = 156K
+  ACTIVE                            : ORIGIN = 0x00027000, LENGTH = 160K
+  DFU                               : ORIGIN = 0x0004F000, LENGTH = 164K
+  FLASH                             : ORIGIN = 0x00078000, LENGTH = 28K
+  BOOTLOADER_STATE                  : ORIGIN = 0x0007F000, LENGTH = 4K
+  /* The MBR keeps its state in the first 8 bytes of RAM */
+  RAM                               : ORIGIN = 0x20000008, LENGTH = 128K - 8 - 256
+  /* 08-ble-watering's last panic, which the stack must not overwrite. Must
+     match 08-ble-watering/memory.x */
+  CRASH                             : ORIGIN = 0x20020000 - 256, LENGTH = 256
+  uicr_bootloader_start_address (r) : ORIGIN = 0x10001014, LENGTH = 0x4
+}
+
+__bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
+__bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
+
+__bootloader_active_start = ORIGIN(ACTIVE);
+__bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE);
+
+__bootloader_dfu_start = ORIGIN(DFU);
+__bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
+
+/* Tells the MBR where to find the bootloader */
+SECTIONS
+{
+  .uicr_bootloader_start_address :
+  {
+    LONG(ORIGIN(FLASH))
+  } > uicr_bootloader_start_address
+}
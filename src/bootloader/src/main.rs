@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+
+use cortex_m_rt::{entry, exception};
+use embassy_boot_nrf::{BootLoader, BootLoaderConfig, WatchdogFlash};
+use embassy_nrf::{
+    gpio::{Level, Output, OutputDrive},
+    nvmc::Nvmc,
+    wdt::{self, HaltConfig, SleepConfig},
+};
+use embassy_sync::blocking_mutex::Mutex;
+use {defmt_rtt as _, panic_probe as _};
+
+/// Swaps in a pending update from the DFU partition, or reverts one that was
+/// never marked as booted, then jumps to the application in the ACTIVE
+/// partition. The MBR starts us before the softdevice is enabled, so the
+/// flash can be written through the NVMC directly.
+///
+/// The watchdog is started here and keeps running into the application, which
+/// can't reconfigure it, so the timeout must match `08-ble-watering`'s
+/// `watchdog.rs`.
+#[entry]
+fn main() -> ! {
+    let p = embassy_nrf::init(Default::default());
+
+    // GPIOs come out of reset disconnected. Hold the pump of `08-ble-watering`
+    // off while a swap runs; the pin keeps its configuration into the app.
+    core::mem::forget(Output::new(p.P0_03, Level::Low, OutputDrive::Standard));
+
+    let mut wdt_config = wdt::Config::default();
+    wdt_config.timeout_ticks = 32_768 * 30;
+    wdt_config.action_during_sleep = SleepConfig::RUN;
+    wdt_config.action_during_debug_halt = HaltConfig::PAUSE;
+
+    let flash = WatchdogFlash::start(Nvmc::new(p.NVMC), p.WDT, &wdt_config);
+    let flash = Mutex::new(RefCell::new(flash));
+
+    let config = BootLoaderConfig::from_linkerfile_blocking(&flash, &flash, &flash);
+    let active_offset = config.active.offset();
+    let bootloader: BootLoader = BootLoader::prepare(config);
+
+    defmt::info!("Booting application at {:#x}", active_offset);
+    unsafe { bootloader.load(active_offset) }
+}
+
+#[no_mangle]
+#[cfg_attr(target_os = "none", link_section = ".HardFault.user")]
+unsafe extern "C" fn HardFault() {
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+#[exception]
+unsafe fn DefaultHandler(_: i16) -> ! {
+    const SCB_ICSR: *const u32 = 0xE000_ED04 as *const u32;
+    let irqn = core::ptr::read_volatile(SCB_ICSR) as u8 as i16 - 16;
+
+    panic!("DefaultHandler #{:?}", irqn);
+}
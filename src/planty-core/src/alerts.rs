@@ -0,0 +1,156 @@
+//! Melodies for the speaker and when they may be played.
+
+/// A note of `frequency_hz`, or a rest when that is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tone {
+    pub frequency_hz: u16,
+    pub duration_ms: u16,
+}
+
+const fn tone(frequency_hz: u16, duration_ms: u16) -> Tone {
+    Tone {
+        frequency_hz,
+        duration_ms,
+    }
+}
+
+const fn rest(duration_ms: u16) -> Tone {
+    tone(0, duration_ms)
+}
+
+/// Range the micro:bit speaker reproduces reasonably well.
+pub const MIN_FREQUENCY_HZ: u16 = 200;
+pub const MAX_FREQUENCY_HZ: u16 = 5000;
+/// No melody should hold up the speaker for longer than this.
+pub const MAX_MELODY_MS: u32 = 3000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alert {
+    /// The soil is dry and the controller can't water it, e.g. because the
+    /// reservoir is empty or the batteries are too weak for the pump.
+    NeedsWater,
+    CalibrationCaptured,
+    BlePaired,
+    Fault,
+    /// A button press the controller can't act on right now.
+    Rejected,
+}
+
+impl Alert {
+    pub const ALL: [Alert; 5] = [
+        Alert::NeedsWater,
+        Alert::CalibrationCaptured,
+        Alert::BlePaired,
+        Alert::Fault,
+        Alert::Rejected,
+    ];
+
+    pub const fn melody(self) -> &'static [Tone] {
+        match self {
+            Alert::NeedsWater => NEEDS_WATER,
+            Alert::CalibrationCaptured => CALIBRATION_CAPTURED,
+            Alert::BlePaired => BLE_PAIRED,
+            Alert::Fault => FAULT,
+            Alert::Rejected => REJECTED,
+        }
+    }
+}
+
+/// Slow, falling pair, repeated.
+const NEEDS_WATER: &[Tone] = &[
+    tone(784, 300),
+    tone(523, 500),
+    rest(300),
+    tone(784, 300),
+    tone(523, 500),
+];
+/// Quick rising confirmation.
+const CALIBRATION_CAPTURED: &[Tone] = &[tone(1047, 80), rest(40), tone(1568, 120)];
+/// Rising arpeggio.
+const BLE_PAIRED: &[Tone] = &[tone(523, 100), tone(659, 100), tone(784, 150)];
+/// Three sharp beeps.
+const FAULT: &[Tone] = &[
+    tone(2093, 150),
+    rest(100),
+    tone(2093, 150),
+    rest(100),
+    tone(2093, 150),
+];
+/// Short, low buzz.
+const REJECTED: &[Tone] = &[tone(262, 120), rest(60), tone(220, 200)];
+
+pub fn duration_ms(melody: &[Tone]) -> u32 {
+    melody.iter().map(|tone| tone.duration_ms as u32).sum()
+}
+
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A window of the day without alerts, from `start` up to `end`, in minutes
+/// since midnight. It may wrap past midnight; `start == end` is no window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl QuietHours {
+    pub fn contains(&self, minute_of_day: u16) -> bool {
+        let minute = minute_of_day % MINUTES_PER_DAY;
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Alert settings as written over BLE (little endian):
+///
+/// | offset | size | field                               |
+/// |--------|------|-------------------------------------|
+/// | 0      | 1    | muted, non-zero to mute             |
+/// | 1      | 2    | quiet hours start, minutes of day   |
+/// | 3      | 2    | quiet hours end, minutes of day     |
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub muted: bool,
+    pub quiet_hours: QuietHours,
+}
+
+impl Settings {
+    pub const LEN: usize = 5;
+
+    pub const fn new() -> Self {
+        Self {
+            muted: false,
+            quiet_hours: QuietHours { start: 0, end: 0 },
+        }
+    }
+
+    /// `None` when a time is past the end of the day.
+    pub fn decode(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let start = u16::from_le_bytes([bytes[1], bytes[2]]);
+        let end = u16::from_le_bytes([bytes[3], bytes[4]]);
+        if start >= MINUTES_PER_DAY || end >= MINUTES_PER_DAY {
+            return None;
+        }
+        Some(Self {
+            muted: bytes[0] != 0,
+            quiet_hours: QuietHours { start, end },
+        })
+    }
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[0] = self.muted as u8;
+        bytes[1..3].copy_from_slice(&self.quiet_hours.start.to_le_bytes());
+        bytes[3..5].copy_from_slice(&self.quiet_hours.end.to_le_bytes());
+        bytes
+    }
+
+    /// Whether an alert may sound. Quiet hours only apply once the time of
+    /// day is known.
+    pub fn allows(&self, minute_of_day: Option<u16>) -> bool {
+        !self.muted && !minute_of_day.is_some_and(|minute| self.quiet_hours.contains(minute))
+    }
+}
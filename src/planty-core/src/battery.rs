@@ -0,0 +1,39 @@
+//! Supply voltage checks. Starting the pump makes a weak battery sag, and if
+//! it sags below what the nRF52833 needs, the whole controller resets.
+
+/// Below this the pump is not started at all.
+pub const MIN_PUMP_MILLIVOLTS: u16 = 2300;
+/// Below this automatic watering runs for `SHORT_WATERING_MS` only.
+pub const SHORT_WATERING_MILLIVOLTS: u16 = 2500;
+pub const SHORT_WATERING_MS: u64 = 2_000;
+/// Below this we warn that the batteries need replacing.
+pub const LOW_MILLIVOLTS: u16 = 2500;
+
+/// Two alkaline AA cells, roughly empty and full.
+const EMPTY_MILLIVOLTS: u16 = 2000;
+const FULL_MILLIVOLTS: u16 = 3000;
+
+/// How long automatic watering may run at this supply voltage, if at all.
+pub fn watering_ms(vdd_millivolts: u16, requested_ms: u64) -> Option<u64> {
+    if vdd_millivolts < MIN_PUMP_MILLIVOLTS {
+        None
+    } else if vdd_millivolts < SHORT_WATERING_MILLIVOLTS {
+        Some(requested_ms.min(SHORT_WATERING_MS))
+    } else {
+        Some(requested_ms)
+    }
+}
+
+pub fn can_start_pump(vdd_millivolts: u16) -> bool {
+    vdd_millivolts >= MIN_PUMP_MILLIVOLTS
+}
+
+pub fn is_low(vdd_millivolts: u16) -> bool {
+    vdd_millivolts < LOW_MILLIVOLTS
+}
+
+/// Rough state of charge, linear between empty and full.
+pub fn percent(vdd_millivolts: u16) -> u8 {
+    let clamped = vdd_millivolts.clamp(EMPTY_MILLIVOLTS, FULL_MILLIVOLTS);
+    ((clamped - EMPTY_MILLIVOLTS) as u32 * 100 / (FULL_MILLIVOLTS - EMPTY_MILLIVOLTS) as u32) as u8
+}
@@ -0,0 +1,138 @@
+//! Plant status broadcast in the manufacturer specific data of every
+//! advertisement, so a passive scanner can log pots without connecting.
+//! The firmware encodes it and `planty-cli decode-beacon` decodes it.
+//!
+//! Layout (little endian), after the AD length and type bytes:
+//!
+//! | offset | size | field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 2    | company identifier (`COMPANY_ID`)       |
+//! | 2      | 1    | payload version (`PAYLOAD_VERSION`)     |
+//! | 3      | 2    | last moisture reading                   |
+//! | 5      | 2    | moisture threshold                      |
+//! | 7      | 1    | flags (`FLAG_*`)                        |
+//! | 8      | 1    | battery in percent, `0xff` when unknown |
+
+use core::fmt;
+
+/// Bluetooth SIG company identifier reserved for testing.
+pub const COMPANY_ID: u16 = 0xffff;
+pub const PAYLOAD_VERSION: u8 = 1;
+pub const PAYLOAD_LEN: usize = 9;
+
+/// The pump is currently running.
+pub const FLAG_WATERING: u8 = 1 << 0;
+/// The supply voltage is low, replace the batteries.
+pub const FLAG_LOW_BATTERY: u8 = 1 << 1;
+/// The moisture probe can't be trusted, so automatic watering is off; see
+/// `probe`.
+pub const FLAG_PROBE_FAULT: u8 = 1 << 2;
+
+const BATTERY_UNKNOWN: u8 = 0xff;
+const AD_TYPE_MANUFACTURER_SPECIFIC_DATA: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    pub moisture: u16,
+    pub threshold: u16,
+    pub flags: u8,
+    pub battery_percent: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The advertisement has no manufacturer specific data.
+    Missing,
+    /// An AD structure claims to be longer than the advertisement.
+    Truncated,
+    WrongLength(usize),
+    ForeignCompany(u16),
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Missing => write!(f, "no manufacturer specific data"),
+            DecodeError::Truncated => write!(f, "advertisement data is truncated"),
+            DecodeError::WrongLength(len) => {
+                write!(f, "payload is {len} bytes, expected {PAYLOAD_LEN}")
+            }
+            DecodeError::ForeignCompany(id) => write!(f, "company id {id:#06x} is not planty"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported payload version {version}")
+            }
+        }
+    }
+}
+
+impl Status {
+    pub fn encode(&self) -> [u8; PAYLOAD_LEN] {
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[0..2].copy_from_slice(&COMPANY_ID.to_le_bytes());
+        payload[2] = PAYLOAD_VERSION;
+        payload[3..5].copy_from_slice(&self.moisture.to_le_bytes());
+        payload[5..7].copy_from_slice(&self.threshold.to_le_bytes());
+        payload[7] = self.flags;
+        payload[8] = self.battery_percent.unwrap_or(BATTERY_UNKNOWN);
+        payload
+    }
+
+    /// Decodes the manufacturer specific data payload, starting at the
+    /// company identifier.
+    pub fn decode(payload: &[u8]) -> Result<Self, DecodeError> {
+        if payload.len() != PAYLOAD_LEN {
+            return Err(DecodeError::WrongLength(payload.len()));
+        }
+
+        let company_id = u16::from_le_bytes([payload[0], payload[1]]);
+        if company_id != COMPANY_ID {
+            return Err(DecodeError::ForeignCompany(company_id));
+        }
+        if payload[2] != PAYLOAD_VERSION {
+            return Err(DecodeError::UnsupportedVersion(payload[2]));
+        }
+
+        Ok(Self {
+            moisture: u16::from_le_bytes([payload[3], payload[4]]),
+            threshold: u16::from_le_bytes([payload[5], payload[6]]),
+            flags: payload[7],
+            battery_percent: (payload[8] != BATTERY_UNKNOWN).then_some(payload[8]),
+        })
+    }
+
+    /// Finds and decodes the planty status in raw advertisement data, as a
+    /// list of length/type/value AD structures.
+    pub fn decode_advertisement(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut rest = data;
+        while let Some((&len, tail)) = rest.split_first() {
+            let len = len as usize;
+            if len == 0 {
+                break;
+            }
+            if tail.len() < len {
+                return Err(DecodeError::Truncated);
+            }
+
+            let (structure, next) = tail.split_at(len);
+            if structure[0] == AD_TYPE_MANUFACTURER_SPECIFIC_DATA {
+                return Self::decode(&structure[1..]);
+            }
+            rest = next;
+        }
+
+        Err(DecodeError::Missing)
+    }
+
+    pub fn is_watering(&self) -> bool {
+        self.flags & FLAG_WATERING != 0
+    }
+
+    pub fn is_low_battery(&self) -> bool {
+        self.flags & FLAG_LOW_BATTERY != 0
+    }
+
+    pub fn is_probe_fault(&self) -> bool {
+        self.flags & FLAG_PROBE_FAULT != 0
+    }
+}
@@ -0,0 +1,152 @@
+//! The controller's event queue: one lane per priority, so a stop is never
+//! stuck behind routine work.
+//!
+//! Safety events and periodic ticks coalesce with an equal event that is
+//! still pending, since acting on them twice in a row achieves nothing. User
+//! commands are kept one by one. A full lane drops the new event. Both are
+//! counted for diagnostics.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Stopping the pump and faults.
+    Safety = 0,
+    /// Buttons and BLE commands.
+    User = 1,
+    /// Timer driven work, like the regular measurement.
+    Periodic = 2,
+}
+
+impl Priority {
+    /// Highest first.
+    pub const ALL: [Priority; 3] = [Priority::Safety, Priority::User, Priority::Periodic];
+
+    fn coalesces(self) -> bool {
+        self != Priority::User
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Push {
+    Queued,
+    /// An equal event was already pending.
+    Coalesced,
+    /// The lane was full.
+    Dropped,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Indexed by `Priority`.
+    pub dropped: [u16; 3],
+    pub coalesced: u16,
+}
+
+impl Counters {
+    pub const LEN: usize = 8;
+
+    /// Dropped safety, user and periodic events, then coalesced events, each
+    /// as little-endian u16.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        let counts = self.dropped.iter().chain([&self.coalesced]);
+        for (chunk, count) in bytes.chunks_exact_mut(2).zip(counts) {
+            chunk.copy_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// A fixed size FIFO.
+pub(crate) struct Lane<T, const N: usize> {
+    items: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy + PartialEq, const N: usize> Lane<T, N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            items: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn contains(&self, item: &T) -> bool {
+        (0..self.len).any(|i| self.items[(self.head + i) % N].as_ref() == Some(item))
+    }
+
+    pub(crate) fn push(&mut self, item: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[(self.head + self.len) % N] = Some(item);
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Up to `N` pending events per priority.
+pub struct Bus<T, const N: usize> {
+    lanes: [Lane<T, N>; 3],
+    counters: Counters,
+}
+
+impl<T: Copy + PartialEq, const N: usize> Bus<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            lanes: [Lane::new(), Lane::new(), Lane::new()],
+            counters: Counters {
+                dropped: [0; 3],
+                coalesced: 0,
+            },
+        }
+    }
+
+    pub fn push(&mut self, priority: Priority, event: T) -> Push {
+        let lane = &mut self.lanes[priority as usize];
+        if priority.coalesces() && lane.contains(&event) {
+            self.counters.coalesced = self.counters.coalesced.saturating_add(1);
+            Push::Coalesced
+        } else if lane.push(event) {
+            Push::Queued
+        } else {
+            let dropped = &mut self.counters.dropped[priority as usize];
+            *dropped = dropped.saturating_add(1);
+            Push::Dropped
+        }
+    }
+
+    /// The oldest event of the highest priority.
+    pub fn pop(&mut self) -> Option<T> {
+        self.lanes.iter_mut().find_map(Lane::pop)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(Lane::is_empty)
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> Default for Bus<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,283 @@
+//! The controller's state machine as a table, so the firmware, the tests and
+//! the diagram in `states.md` all work from the same transitions.
+//!
+//! Every state and event pair has exactly one row, which either moves to a
+//! state or rejects the event with a reason for whoever sent it. Leaving and
+//! entering a state runs its exit and entry actions; a row that stays in its
+//! state runs neither.
+//!
+//! The pump safety properties are proven with Kani in `proofs.rs`.
+
+use core::fmt;
+
+use crate::pump::Command;
+
+/// No watering, manual or automatic, runs longer than this.
+pub const MAX_WATERING_MS: u64 = 60_000;
+/// Readings this far below the dry reading taken when calibrating still
+/// count as dry enough to water.
+pub const THRESHOLD_BUFFER: u16 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Watering,
+    /// Something is wrong with the hardware; the pump stays off until the
+    /// fault clears.
+    Fault,
+}
+
+impl State {
+    pub const ALL: [State; 3] = [State::Idle, State::Watering, State::Fault];
+
+    pub fn on_entry(self) -> Option<Action> {
+        match self {
+            State::Idle => None,
+            State::Watering => Some(Action::StartPump),
+            State::Fault => Some(Action::StopPump),
+        }
+    }
+
+    pub fn on_exit(self) -> Option<Action> {
+        match self {
+            State::Idle | State::Fault => None,
+            State::Watering => Some(Action::StopPump),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Start the pump until told to stop.
+    Water,
+    /// Stop the pump.
+    WateringComplete,
+    Measure,
+    /// Take the current reading as the new threshold.
+    Calibrate,
+    /// A measurement found the soil dry and the supply good enough to water.
+    SoilDry,
+    Fault,
+    FaultCleared,
+}
+
+impl Event {
+    pub const ALL: [Event; 7] = [
+        Event::Water,
+        Event::WateringComplete,
+        Event::Measure,
+        Event::Calibrate,
+        Event::SoilDry,
+        Event::Fault,
+        Event::FaultCleared,
+    ];
+}
+
+impl From<Command> for Event {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Start => Event::Water,
+            Command::Stop => Event::WateringComplete,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    StartPump,
+    StopPump,
+    Measure,
+    Calibrate,
+}
+
+/// A condition checked by the firmware before a transition is taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Guard {
+    /// The supply can drive the pump.
+    SupplyOk,
+}
+
+impl Guard {
+    /// Why the event is rejected when the guard fails.
+    pub fn rejection(self) -> Rejection {
+        match self {
+            Guard::SupplyOk => Rejection::LowSupply,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    AlreadyWatering,
+    NotWatering,
+    /// Measuring or calibrating while the pump runs would read the water
+    /// rather than the soil.
+    Watering,
+    LowSupply,
+    Fault,
+    NotFaulty,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Go {
+        to: State,
+        guard: Option<Guard>,
+        action: Option<Action>,
+    },
+    Reject(Rejection),
+}
+
+const fn go(to: State, guard: Option<Guard>, action: Option<Action>) -> Rule {
+    Rule::Go { to, guard, action }
+}
+
+#[rustfmt::skip]
+pub const TABLE: &[(State, Event, Rule)] = &[
+    (State::Idle,     Event::Water,            go(State::Watering, Some(Guard::SupplyOk), None)),
+    (State::Idle,     Event::WateringComplete, Rule::Reject(Rejection::NotWatering)),
+    (State::Idle,     Event::Measure,          go(State::Idle, None, Some(Action::Measure))),
+    (State::Idle,     Event::Calibrate,        go(State::Idle, None, Some(Action::Calibrate))),
+    (State::Idle,     Event::SoilDry,          go(State::Watering, Some(Guard::SupplyOk), None)),
+    (State::Watering, Event::Water,            Rule::Reject(Rejection::AlreadyWatering)),
+    (State::Watering, Event::WateringComplete, go(State::Idle, None, None)),
+    (State::Watering, Event::Measure,          Rule::Reject(Rejection::Watering)),
+    (State::Watering, Event::Calibrate,        Rule::Reject(Rejection::Watering)),
+    (State::Watering, Event::SoilDry,          Rule::Reject(Rejection::AlreadyWatering)),
+    (State::Idle,     Event::Fault,            go(State::Fault, None, None)),
+    (State::Idle,     Event::FaultCleared,     Rule::Reject(Rejection::NotFaulty)),
+    (State::Watering, Event::Fault,            go(State::Fault, None, None)),
+    (State::Watering, Event::FaultCleared,     Rule::Reject(Rejection::NotFaulty)),
+    (State::Fault,    Event::Water,            Rule::Reject(Rejection::Fault)),
+    (State::Fault,    Event::WateringComplete, Rule::Reject(Rejection::NotWatering)),
+    // Keep measuring, that is how a sensor fault clears
+    (State::Fault,    Event::Measure,          go(State::Fault, None, Some(Action::Measure))),
+    (State::Fault,    Event::Calibrate,        Rule::Reject(Rejection::Fault)),
+    (State::Fault,    Event::SoilDry,          Rule::Reject(Rejection::Fault)),
+    (State::Fault,    Event::Fault,            Rule::Reject(Rejection::Fault)),
+    (State::Fault,    Event::FaultCleared,     go(State::Idle, None, None)),
+];
+
+/// A transition that passed its guard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub from: State,
+    pub event: Event,
+    pub to: State,
+    pub action: Option<Action>,
+}
+
+impl Transition {
+    /// In order: exit actions, the transition's own action, entry actions.
+    pub fn actions(&self) -> [Option<Action>; 3] {
+        if self.from == self.to {
+            [None, self.action, None]
+        } else {
+            [self.from.on_exit(), self.action, self.to.on_entry()]
+        }
+    }
+}
+
+/// The row for `state` and `event`.
+pub fn rule(state: State, event: Event) -> Rule {
+    TABLE
+        .iter()
+        .find(|(from, on, _)| *from == state && *on == event)
+        .map(|&(_, _, rule)| rule)
+        // The tests make sure of it
+        .expect("every state and event has a row")
+}
+
+/// Looks up and guards the transition for `event` in `state`. `check` tells
+/// whether a guard holds.
+pub fn step(
+    state: State,
+    event: Event,
+    check: impl FnOnce(Guard) -> bool,
+) -> Result<Transition, Rejection> {
+    match rule(state, event) {
+        Rule::Reject(rejection) => Err(rejection),
+        Rule::Go {
+            guard: Some(guard), ..
+        } if !check(guard) => Err(guard.rejection()),
+        Rule::Go { to, action, .. } => Ok(Transition {
+            from: state,
+            event,
+            to,
+            action,
+        }),
+    }
+}
+
+/// When watering that starts at `now_ms` has to stop. Automatic watering
+/// asks for `requested_ms`, manual watering runs until stopped; both are cut
+/// off at `MAX_WATERING_MS`.
+pub fn watering_deadline(now_ms: u64, requested_ms: Option<u64>) -> u64 {
+    let duration = requested_ms.map_or(MAX_WATERING_MS, |ms| ms.min(MAX_WATERING_MS));
+    now_ms.saturating_add(duration)
+}
+
+/// When the pump has to stop once `transition` is taken at `now_ms`, given
+/// the deadline before it. Entering `Watering` sets a new one, for
+/// `watering_ms` if a dry measurement asked for it; leaving it clears it.
+pub fn deadline_after(
+    transition: &Transition,
+    now_ms: u64,
+    deadline: Option<u64>,
+    watering_ms: u64,
+) -> Option<u64> {
+    if transition.to == transition.from {
+        return deadline;
+    }
+    (transition.to == State::Watering).then(|| {
+        // Manual watering runs until stopped, up to the maximum
+        let requested = match transition.event {
+            Event::SoilDry => Some(watering_ms),
+            _ => None,
+        };
+        watering_deadline(now_ms, requested)
+    })
+}
+
+/// The threshold for a calibration reading taken in soil that is just dry
+/// enough to water.
+pub fn threshold(dry_reading: u16) -> u16 {
+    dry_reading.saturating_sub(THRESHOLD_BUFFER)
+}
+
+/// Writes the table as a Mermaid state diagram.
+pub fn diagram(out: &mut impl fmt::Write) -> fmt::Result {
+    writeln!(out, "stateDiagram-v2")?;
+    writeln!(out, "    [*] --> {:?}", State::Idle)?;
+    for state in State::ALL {
+        if let Some(action) = state.on_entry() {
+            writeln!(out, "    {:?}: entry / {:?}", state, action)?;
+        }
+        if let Some(action) = state.on_exit() {
+            writeln!(out, "    {:?}: exit / {:?}", state, action)?;
+        }
+    }
+    for &(from, event, rule) in TABLE {
+        if let Rule::Go { to, guard, action } = rule {
+            write!(out, "    {:?} --> {:?}: {:?}", from, to, event)?;
+            if let Some(guard) = guard {
+                write!(out, " [{:?}]", guard)?;
+            }
+            if let Some(action) = action {
+                write!(out, " / {:?}", action)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    for state in State::ALL {
+        writeln!(out, "    note right of {:?}", state)?;
+        writeln!(out, "        Rejects:")?;
+        for &(from, event, rule) in TABLE {
+            if let (true, Rule::Reject(rejection)) = (from == state, rule) {
+                writeln!(out, "        {:?} ({:?})", event, rejection)?;
+            }
+        }
+        writeln!(out, "    end note")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! The record of the last panic, kept across resets in a RAM region that
+//! neither the application nor the bootloader initializes, `CRASH` in both
+//! `memory.x` files. It survives resets but not a power cycle, so it is only
+//! believed if its magic number and CRC check out.
+//!
+//! Encoding of the diagnostics characteristic (little endian), empty when
+//! there is no record:
+//!
+//! | offset | size | field                          |
+//! |--------|------|--------------------------------|
+//! | 0      | 8    | uptime at the panic, in ms     |
+//! | 8      | 4    | line                           |
+//! | 12     | 1    | file length `F`                |
+//! | 13     | `F`  | file, UTF-8                    |
+//! | 13+F   | 1    | message length `M`             |
+//! | 14+F   | `M`  | message, UTF-8                 |
+//!
+//! In RAM the encoding follows a header of the magic number, the CRC-32 of
+//! the encoding and its length, a `u32`, `u32` and `u16`.
+
+use core::fmt::{self, Write as _};
+
+pub const FILE_LEN: usize = 48;
+pub const MESSAGE_LEN: usize = 96;
+pub const ENCODED_LEN: usize = 14 + FILE_LEN + MESSAGE_LEN;
+
+/// Size of the `CRASH` region.
+pub const SLOT_LEN: usize = 256;
+const HEADER_LEN: usize = 10;
+const MAGIC: u32 = 0x504e_4943; // "PNIC"
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    /// The file or message is not UTF-8.
+    InvalidText,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "crash record is truncated"),
+            DecodeError::InvalidText => write!(f, "crash record holds invalid UTF-8"),
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub uptime_ms: u64,
+    pub line: u32,
+    file: Text<FILE_LEN>,
+    message: Text<MESSAGE_LEN>,
+}
+
+impl Record {
+    /// Keeps as much of `file` and `message` as fits.
+    pub fn new(uptime_ms: u64, file: &str, line: u32, message: fmt::Arguments) -> Self {
+        let mut record = Self {
+            uptime_ms,
+            line,
+            file: Text::new(),
+            message: Text::new(),
+        };
+        let _ = record.file.write_str(file);
+        let _ = record.message.write_fmt(message);
+        record
+    }
+
+    pub fn file(&self) -> &str {
+        self.file.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    /// The characteristic value, in the first `len` bytes.
+    pub fn encode(&self) -> ([u8; ENCODED_LEN], usize) {
+        let mut encoded = [0; ENCODED_LEN];
+        encoded[0..8].copy_from_slice(&self.uptime_ms.to_le_bytes());
+        encoded[8..12].copy_from_slice(&self.line.to_le_bytes());
+        let mut len = 12;
+        for text in [self.file(), self.message()] {
+            encoded[len] = text.len() as u8;
+            encoded[len + 1..len + 1 + text.len()].copy_from_slice(text.as_bytes());
+            len += 1 + text.len();
+        }
+        (encoded, len)
+    }
+
+    /// Decodes a characteristic value that isn't empty.
+    pub fn decode(value: &[u8]) -> Result<Self, DecodeError> {
+        let (uptime_ms, rest) = value
+            .split_first_chunk::<8>()
+            .ok_or(DecodeError::Truncated)?;
+        let (line, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(DecodeError::Truncated)?;
+        let (file, rest) = text(rest)?;
+        let (message, _) = text(rest)?;
+        Ok(Self::new(
+            u64::from_le_bytes(*uptime_ms),
+            file,
+            u32::from_le_bytes(*line),
+            format_args!("{message}"),
+        ))
+    }
+
+    /// What the panic handler leaves in the `CRASH` region.
+    pub fn store(&self) -> [u8; SLOT_LEN] {
+        let (encoded, len) = self.encode();
+        let mut slot = [0; SLOT_LEN];
+        slot[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        slot[4..8].copy_from_slice(&crc32(&encoded[..len]).to_le_bytes());
+        slot[8..10].copy_from_slice(&(len as u16).to_le_bytes());
+        slot[HEADER_LEN..HEADER_LEN + len].copy_from_slice(&encoded[..len]);
+        slot
+    }
+
+    /// The record in the `CRASH` region, unless it holds what RAM comes up
+    /// with after a power cycle, or a cleared record.
+    pub fn load(slot: &[u8; SLOT_LEN]) -> Option<Self> {
+        let magic = u32::from_le_bytes(slot[0..4].try_into().unwrap());
+        let crc = u32::from_le_bytes(slot[4..8].try_into().unwrap());
+        let len = u16::from_le_bytes(slot[8..10].try_into().unwrap()) as usize;
+        if magic != MAGIC || len > ENCODED_LEN {
+            return None;
+        }
+        let encoded = &slot[HEADER_LEN..HEADER_LEN + len];
+        if crc32(encoded) != crc {
+            return None;
+        }
+        Self::decode(encoded).ok()
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "panicked after {:.1} s at {}:{}: {}",
+            self.uptime_ms as f64 / 1000.0,
+            self.file(),
+            self.line,
+            self.message()
+        )
+    }
+}
+
+/// A length prefixed string, followed by whatever comes after it.
+fn text(value: &[u8]) -> Result<(&str, &[u8]), DecodeError> {
+    let (&len, rest) = value.split_first().ok_or(DecodeError::Truncated)?;
+    if rest.len() < len as usize {
+        return Err(DecodeError::Truncated);
+    }
+    let (text, rest) = rest.split_at(len as usize);
+    let text = core::str::from_utf8(text).map_err(|_| DecodeError::InvalidText)?;
+    Ok((text, rest))
+}
+
+/// CRC-32 as in zlib and Ethernet. The record is small and written once per
+/// crash, so a bitwise one does.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Fixed size UTF-8 buffer that keeps as much of what is written as fits,
+/// cut at a character boundary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Text<const N: usize> {
+    len: u8,
+    bytes: [u8; N],
+}
+
+impl<const N: usize> Text<N> {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            bytes: [0; N],
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only ever written whole characters
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("?")
+    }
+}
+
+impl<const N: usize> fmt::Write for Text<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = self.len as usize;
+            if len + c.len_utf8() > N {
+                break;
+            }
+            c.encode_utf8(&mut self.bytes[len..]);
+            self.len += c.len_utf8() as u8;
+        }
+        Ok(())
+    }
+}
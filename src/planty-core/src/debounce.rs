@@ -0,0 +1,178 @@
+//! Debouncing strategies for a bouncing digital input.
+//!
+//! Feed every raw level change to `Debouncer::input` and call
+//! `Debouncer::poll` once `Debouncer::deadline` has passed; both take the
+//! current time in milliseconds. A debounced change comes back as an `Edge`
+//! stamped with the time the input first left the previous level, which is
+//! the best guess of when the button actually moved.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Samples the input every `sample_ms` and counts up while it is high and
+    /// down while it is low, between 0 and `samples`. The output switches when
+    /// the count reaches either end. Rejects glitches and tolerates bounce
+    /// that never settles completely.
+    Integrator { sample_ms: u64, samples: u8 },
+    /// Reports the first edge right away, then ignores the input for
+    /// `lockout_ms`. The quickest response, but glitches get through.
+    Lockout { lockout_ms: u64 },
+    /// Reports a level once the input has held it for `stable_ms`.
+    Stable { stable_ms: u64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub high: bool,
+    pub at: u64,
+}
+
+pub struct Debouncer {
+    strategy: Strategy,
+    output: bool,
+    raw: bool,
+    /// When `raw` last changed.
+    changed_at: u64,
+    /// When the input first left `output`, since it last rested there.
+    left_at: Option<u64>,
+    /// Integrator count.
+    count: u8,
+    deadline: Option<u64>,
+}
+
+impl Debouncer {
+    pub fn new(strategy: Strategy, level: bool) -> Self {
+        let count = match strategy {
+            Strategy::Integrator { samples, .. } if level => samples,
+            _ => 0,
+        };
+        Self {
+            strategy,
+            output: level,
+            raw: level,
+            changed_at: 0,
+            left_at: None,
+            count,
+            deadline: None,
+        }
+    }
+
+    /// The debounced level.
+    pub fn level(&self) -> bool {
+        self.output
+    }
+
+    /// The raw level as last reported to `input`.
+    pub fn raw(&self) -> bool {
+        self.raw
+    }
+
+    /// When `poll` has to be called next, if anything is pending.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Feeds a raw level change at `now`.
+    pub fn input(&mut self, high: bool, now: u64) -> Option<Edge> {
+        if high == self.raw {
+            return None;
+        }
+        self.raw = high;
+        self.changed_at = now;
+        if high != self.output {
+            self.left_at.get_or_insert(now);
+        }
+
+        match self.strategy {
+            Strategy::Stable { stable_ms } => {
+                self.deadline = Some(now + stable_ms);
+                None
+            }
+            Strategy::Lockout { lockout_ms } => {
+                // Changes during the lockout are picked up when it ends
+                if self.deadline.is_some() {
+                    return None;
+                }
+                self.deadline = Some(now + lockout_ms);
+                self.switch(now)
+            }
+            Strategy::Integrator { sample_ms, .. } => {
+                // Keep the sampling phase if already sampling
+                if self.deadline.is_none() {
+                    self.deadline = Some(now + sample_ms);
+                }
+                None
+            }
+        }
+    }
+
+    /// Settles whatever was due by `now`.
+    pub fn poll(&mut self, now: u64) -> Option<Edge> {
+        let deadline = self.deadline.filter(|&deadline| deadline <= now)?;
+
+        match self.strategy {
+            Strategy::Stable { .. } => {
+                self.deadline = None;
+                self.settle(self.changed_at)
+            }
+            Strategy::Lockout { lockout_ms } => {
+                if self.raw == self.output {
+                    self.deadline = None;
+                    self.left_at = None;
+                    return None;
+                }
+                // The input changed during the lockout and ended up elsewhere
+                self.deadline = Some(deadline + lockout_ms);
+                self.switch(self.changed_at)
+            }
+            Strategy::Integrator { sample_ms, samples } => {
+                let mut edge = None;
+                let mut sample_at = deadline;
+                while sample_at <= now {
+                    if self.raw {
+                        self.count = (self.count + 1).min(samples);
+                    } else {
+                        self.count = self.count.saturating_sub(1);
+                    }
+                    if self.count == samples && !self.output || self.count == 0 && self.output {
+                        edge = self.switch(sample_at);
+                    }
+                    let settled = if self.raw {
+                        self.count == samples
+                    } else {
+                        self.count == 0
+                    };
+                    if settled {
+                        // Back at the output level, or just switched to it
+                        self.left_at = None;
+                        self.deadline = None;
+                        return edge;
+                    }
+                    sample_at += sample_ms;
+                }
+                self.deadline = Some(sample_at);
+                edge
+            }
+        }
+    }
+
+    /// Moves the output to the raw level, or forgets the excursion if the
+    /// input came back.
+    fn settle(&mut self, fallback_at: u64) -> Option<Edge> {
+        let edge = self.switch(fallback_at);
+        self.left_at = None;
+        edge
+    }
+
+    /// Moves the output to the raw level, if it isn't there already.
+    fn switch(&mut self, fallback_at: u64) -> Option<Edge> {
+        if self.raw == self.output {
+            return None;
+        }
+        self.output = self.raw;
+        let at = self.left_at.take().unwrap_or(fallback_at);
+        Some(Edge {
+            high: self.output,
+            at,
+        })
+    }
+}
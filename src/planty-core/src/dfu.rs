@@ -0,0 +1,226 @@
+//! Writing a firmware update into the DFU partition, chunk by chunk as it
+//! arrives over BLE, and verifying it before the bootloader gets to see it.
+//!
+//! Images are signed with ed25519 over their SHA-512, the way `planty-cli
+//! sign` signs them. The flash, the key and the bootloader are all passed in,
+//! so the firmware and the host tests can bring their own.
+
+use core::future::Future;
+
+use ed25519::{signature::Verifier, Signature};
+use embedded_storage_async::nor_flash::NorFlash;
+use sha2::{Digest, Sha512};
+
+pub const MAX_CHUNK_LEN: usize = 240;
+pub const SIGNATURE_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    None = 0,
+    InvalidRequest = 1,
+    NotStarted = 2,
+    TooLarge = 3,
+    UnexpectedOffset = 4,
+    Misaligned = 5,
+    Incomplete = 6,
+    BadSignature = 7,
+    Flash = 8,
+    Busy = 9,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Idle = 0,
+    Receiving = 1,
+    Verified = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub phase: Phase,
+    pub error: Error,
+    pub next_offset: u32,
+}
+
+impl Status {
+    pub const LEN: usize = 6;
+
+    /// Phase, error, then the next offset as a little-endian u32.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[0] = self.phase as u8;
+        bytes[1] = self.error as u8;
+        bytes[2..6].copy_from_slice(&self.next_offset.to_le_bytes());
+        bytes
+    }
+}
+
+/// Hands a verified image over to the bootloader, which swaps it in on the
+/// next boot.
+pub trait Bootloader {
+    /// Marks the image of `len` bytes that was checked against `signature`.
+    /// A bootloader that only takes signed images, like embassy-boot with
+    /// one of its ed25519 features, checks it once more itself.
+    fn mark_updated(
+        &mut self,
+        len: u32,
+        signature: &[u8; SIGNATURE_LEN],
+    ) -> impl Future<Output = Result<(), Error>>;
+}
+
+enum Progress {
+    Idle,
+    Receiving {
+        len: u32,
+        written: u32,
+        signature: [u8; SIGNATURE_LEN],
+    },
+    Verified,
+}
+
+/// Writes an image into the DFU partition `F` and verifies it.
+pub struct ImageWriter<F> {
+    dfu: F,
+    progress: Progress,
+}
+
+impl<F: NorFlash> ImageWriter<F> {
+    pub fn new(dfu: F) -> Self {
+        Self {
+            dfu,
+            progress: Progress::Idle,
+        }
+    }
+
+    pub fn status(&self, error: Error) -> Status {
+        let (phase, next_offset) = match self.progress {
+            Progress::Idle => (Phase::Idle, 0),
+            Progress::Receiving { written, .. } => (Phase::Receiving, written),
+            Progress::Verified => (Phase::Verified, 0),
+        };
+        Status {
+            phase,
+            error,
+            next_offset,
+        }
+    }
+
+    /// The bootloader needs one spare page in the DFU partition to swap.
+    pub fn max_image_len(&self) -> u32 {
+        (self.dfu.capacity() - F::ERASE_SIZE) as u32
+    }
+
+    /// Erases enough of the DFU partition for an image of `len` bytes. Starts
+    /// over if an image was already on its way.
+    pub async fn start(&mut self, len: u32, signature: [u8; SIGNATURE_LEN]) -> Result<(), Error> {
+        self.progress = Progress::Idle;
+        if len == 0 || len > self.max_image_len() {
+            return Err(Error::TooLarge);
+        }
+
+        let erase_len = (len as usize).div_ceil(F::ERASE_SIZE) * F::ERASE_SIZE;
+        self.dfu
+            .erase(0, erase_len as u32)
+            .await
+            .map_err(|_| Error::Flash)?;
+
+        self.progress = Progress::Receiving {
+            len,
+            written: 0,
+            signature,
+        };
+        Ok(())
+    }
+
+    /// Writes the next chunk. Chunks have to arrive in order, and all but the
+    /// last one have to be a multiple of the flash write size.
+    pub async fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        let Progress::Receiving { len, written, .. } = &mut self.progress else {
+            return Err(Error::NotStarted);
+        };
+        if data.is_empty() || data.len() > MAX_CHUNK_LEN {
+            return Err(Error::InvalidRequest);
+        }
+        if offset != *written {
+            return Err(Error::UnexpectedOffset);
+        }
+        let end = offset + data.len() as u32;
+        if end > *len {
+            return Err(Error::TooLarge);
+        }
+
+        let aligned = data.len() - data.len() % F::WRITE_SIZE;
+        if aligned != data.len() && end != *len {
+            return Err(Error::Misaligned);
+        }
+
+        self.dfu
+            .write(offset, &data[..aligned])
+            .await
+            .map_err(|_| Error::Flash)?;
+
+        // Pad the tail of the last chunk with erased bytes
+        if aligned != data.len() {
+            let mut tail = [0xff; MAX_CHUNK_LEN];
+            tail[..data.len() - aligned].copy_from_slice(&data[aligned..]);
+            self.dfu
+                .write(offset + aligned as u32, &tail[..F::WRITE_SIZE])
+                .await
+                .map_err(|_| Error::Flash)?;
+        }
+
+        *written = end;
+        Ok(())
+    }
+
+    /// Reads the image back from flash, checks the signature given at start
+    /// against `public_key` and only then marks it for `bootloader`.
+    pub async fn finish(
+        &mut self,
+        public_key: &impl Verifier<Signature>,
+        bootloader: &mut impl Bootloader,
+    ) -> Result<(), Error> {
+        let Progress::Receiving {
+            len,
+            written,
+            signature,
+        } = self.progress
+        else {
+            return Err(Error::NotStarted);
+        };
+        if written != len {
+            return Err(Error::Incomplete);
+        }
+
+        let mut hasher = Sha512::new();
+        let mut buf = [0; MAX_CHUNK_LEN];
+        let mut offset = 0;
+        while offset < len {
+            let chunk_len = (len - offset).min(buf.len() as u32) as usize;
+            self.dfu
+                .read(offset, &mut buf[..chunk_len])
+                .await
+                .map_err(|_| Error::Flash)?;
+            hasher.update(&buf[..chunk_len]);
+            offset += chunk_len as u32;
+        }
+
+        // Whatever happens next, this image has to be uploaded again
+        self.progress = Progress::Idle;
+        let digest = hasher.finalize();
+        if public_key
+            .verify(&digest, &Signature::from_bytes(&signature))
+            .is_err()
+        {
+            return Err(Error::BadSignature);
+        }
+        bootloader.mark_updated(len, &signature).await?;
+
+        self.progress = Progress::Verified;
+        Ok(())
+    }
+
+    pub fn abort(&mut self) {
+        self.progress = Progress::Idle;
+    }
+}
@@ -0,0 +1,142 @@
+//! What the 5x5 LED matrix shows, as a pure function of the controller status.
+//!
+//! A frame is five rows from top to bottom. In each row bit 4 is the leftmost
+//! column and bit 0 the rightmost, the same as the micro:bit fonts.
+//!
+//! There is no glyph for an empty reservoir: nothing on the board senses the
+//! water level, and a reading that doesn't rise after watering can as well
+//! be water still soaking in, or a probe away from the outlet.
+
+pub type Frame = [u8; 5];
+
+/// Readings of the probe in dry air and in water; see `sensor.rs`.
+pub const DRY_READING: u16 = 2840;
+pub const WET_READING: u16 = 1180;
+
+/// Ticks each fault glyph, and the normal view between them, stays up.
+pub const FAULT_TICKS: u32 = 5;
+
+const BLE_PIXEL: Frame = [0b00001, 0, 0, 0, 0];
+/// Shown until the first reading.
+const NO_READING: Frame = [0, 0, 0b01110, 0, 0];
+
+/// Manual watering ran for `control::MAX_WATERING_MS` without being stopped.
+pub const PUMP_TIMEOUT: Frame = [0b11111, 0b01010, 0b00100, 0b01010, 0b11111];
+pub const LOW_BATTERY: Frame = [0b00100, 0b01010, 0b01010, 0b01010, 0b01110];
+/// The moisture reading can't be trusted; see `probe`.
+pub const PROBE_FAULT: Frame = [0b01110, 0b10001, 0b00110, 0, 0b00100];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Faults {
+    /// Until the pump next starts.
+    pub pump_timeout: bool,
+    pub low_battery: bool,
+    pub probe: bool,
+}
+
+impl Faults {
+    pub const NONE: Self = Self {
+        pump_timeout: false,
+        low_battery: false,
+        probe: false,
+    };
+
+    fn glyphs(&self) -> impl Iterator<Item = Frame> {
+        [
+            (self.pump_timeout, PUMP_TIMEOUT),
+            (self.low_battery, LOW_BATTERY),
+            (self.probe, PROBE_FAULT),
+        ]
+        .into_iter()
+        .filter_map(|(active, glyph)| active.then_some(glyph))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Last moisture reading, `None` until the first measurement.
+    pub moisture: Option<u16>,
+    pub watering: bool,
+    /// At least one BLE central is connected.
+    pub connected: bool,
+    pub faults: Faults,
+}
+
+impl Status {
+    /// Nothing measured yet and no faults.
+    pub const fn new() -> Self {
+        Self {
+            moisture: None,
+            watering: false,
+            connected: false,
+            faults: Faults::NONE,
+        }
+    }
+
+    /// Whether the display should stay on even when nobody asked for it. A
+    /// low battery doesn't count: it lasts for weeks, and keeping the matrix
+    /// on would only drain the battery faster.
+    pub fn needs_attention(&self) -> bool {
+        self.watering || self.faults.pump_timeout || self.faults.probe
+    }
+}
+
+/// The frame to show at `tick`, which advances once per animation step.
+///
+/// Active faults take turns with the normal view, so a persistent one like a
+/// low battery doesn't hide the moisture level. The normal view is the
+/// watering animation while the pump runs and the moisture bar graph
+/// otherwise, with the top right pixel lit while a central is connected.
+pub fn render(status: &Status, tick: u32) -> Frame {
+    let fault_count = status.faults.glyphs().count() as u32;
+    if fault_count > 0 {
+        let slot = tick / FAULT_TICKS % (fault_count + 1);
+        if let Some(glyph) = status.faults.glyphs().nth(slot as usize) {
+            return glyph;
+        }
+    }
+
+    let mut frame = if status.watering {
+        watering(tick)
+    } else {
+        match status.moisture {
+            Some(reading) => bar_graph(moisture_level(reading)),
+            None => NO_READING,
+        }
+    };
+    if status.connected {
+        overlay(&mut frame, &BLE_PIXEL);
+    }
+    frame
+}
+
+/// Moisture on a scale from 0 (dry) to 5 (wet), rounded to the nearest step.
+pub fn moisture_level(reading: u16) -> u8 {
+    let reading = reading.clamp(WET_READING, DRY_READING);
+    let range = (DRY_READING - WET_READING) as u32;
+    let wetness = (DRY_READING - reading) as u32;
+    ((wetness * 5 + range / 2) / range) as u8
+}
+
+/// A bar over the four left columns, `level` rows high.
+fn bar_graph(level: u8) -> Frame {
+    let mut frame = [0; 5];
+    for row in frame.iter_mut().rev().take(level as usize) {
+        *row = 0b11110;
+    }
+    frame
+}
+
+/// Two drops falling onto the soil in the bottom row.
+fn watering(tick: u32) -> Frame {
+    let mut frame = [0, 0, 0, 0, 0b11110];
+    frame[(tick % 4) as usize] |= 0b01000;
+    frame[((tick + 2) % 4) as usize] |= 0b00100;
+    frame
+}
+
+fn overlay(frame: &mut Frame, other: &Frame) {
+    for (row, other) in frame.iter_mut().zip(other) {
+        *row |= other;
+    }
+}
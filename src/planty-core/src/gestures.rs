@@ -0,0 +1,155 @@
+//! Button gestures from debounced level changes.
+//!
+//! Feed every debounced change to `Recognizer::edge` and call
+//! `Recognizer::poll` once `Recognizer::deadline` has passed. Both take the
+//! current time in milliseconds and report recognized gestures through a
+//! callback. Edges are given as levels, so a repeated level, e.g. after a
+//! missed edge, is ignored rather than inverting press and release.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// Pressed and released, and not pressed again within the double click
+    /// window.
+    Short(Button),
+    /// Held for `Config::long_press_ms`. Reported while still held.
+    Long(Button),
+    /// Released after a long press.
+    LongEnd(Button),
+    /// Pressed a second time within the double click window.
+    Double(Button),
+    /// Both buttons pressed before either became a long press.
+    Chord,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub long_press_ms: u64,
+    /// How long to wait for a second click. Zero turns double clicks off and
+    /// reports short presses on release.
+    pub double_click_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            long_press_ms: 800,
+            double_click_ms: 300,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    Pressed {
+        since: u64,
+    },
+    /// The long press was reported, waiting for the release.
+    Held,
+    /// Released after a short press, waiting for a second click.
+    Released {
+        at: u64,
+    },
+    /// Already reported as a chord or double click, waiting for the release.
+    Consumed,
+}
+
+pub struct Recognizer {
+    config: Config,
+    a: State,
+    b: State,
+}
+
+impl Recognizer {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            a: State::Idle,
+            b: State::Idle,
+        }
+    }
+
+    fn state(&mut self, button: Button) -> &mut State {
+        match button {
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+        }
+    }
+
+    /// When `poll` has to be called next, if anything is pending.
+    pub fn deadline(&self) -> Option<u64> {
+        [self.a, self.b]
+            .into_iter()
+            .filter_map(|state| self.state_deadline(state))
+            .min()
+    }
+
+    fn state_deadline(&self, state: State) -> Option<u64> {
+        match state {
+            State::Pressed { since } => Some(since + self.config.long_press_ms),
+            State::Released { at } => Some(at + self.config.double_click_ms),
+            _ => None,
+        }
+    }
+
+    /// Reports the gestures whose deadline has passed by `now`.
+    pub fn poll(&mut self, now: u64, mut emit: impl FnMut(Gesture)) {
+        for button in [Button::A, Button::B] {
+            let state = *self.state(button);
+            if self
+                .state_deadline(state)
+                .is_some_and(|deadline| now >= deadline)
+            {
+                let (gesture, next) = match state {
+                    State::Pressed { .. } => (Gesture::Long(button), State::Held),
+                    _ => (Gesture::Short(button), State::Idle),
+                };
+                *self.state(button) = next;
+                emit(gesture);
+            }
+        }
+    }
+
+    /// Feeds a debounced level change of `button` at `now`.
+    pub fn edge(&mut self, button: Button, pressed: bool, now: u64, mut emit: impl FnMut(Gesture)) {
+        self.poll(now, &mut emit);
+
+        let other = match button {
+            Button::A => Button::B,
+            Button::B => Button::A,
+        };
+        let state = *self.state(button);
+        let next = match (state, pressed) {
+            (State::Idle | State::Released { .. }, true)
+                if matches!(self.state(other), State::Pressed { .. }) =>
+            {
+                *self.state(other) = State::Consumed;
+                emit(Gesture::Chord);
+                State::Consumed
+            }
+            (State::Idle, true) => State::Pressed { since: now },
+            (State::Released { .. }, true) => {
+                emit(Gesture::Double(button));
+                State::Consumed
+            }
+            (State::Pressed { .. }, false) => State::Released { at: now },
+            (State::Held, false) => {
+                emit(Gesture::LongEnd(button));
+                State::Idle
+            }
+            (State::Consumed, false) => State::Idle,
+            // Same level as before, an edge went missing
+            (state, _) => state,
+        };
+        *self.state(button) = next;
+
+        // With double clicks off a release is reported right away
+        self.poll(now, emit);
+    }
+}
@@ -0,0 +1,37 @@
+//! Hardware independent parts of `08-ble-watering`, kept out of the firmware
+//! crate so they can be tested on the host:
+//!
+//! ```sh
+//! cargo test -p planty-core --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! The task loops in `tasks` need embassy and are behind the `tasks` feature,
+//! and firmware updates in `dfu` need a flash and ed25519, behind `dfu`. The
+//! firmware always enables both:
+//!
+//! ```sh
+//! cargo test -p planty-core --features tasks,dfu --target x86_64-unknown-linux-gnu
+//! ```
+#![no_std]
+
+pub mod alerts;
+pub mod battery;
+pub mod beacon;
+pub mod bus;
+pub mod control;
+pub mod crash;
+pub mod debounce;
+#[cfg(feature = "dfu")]
+pub mod dfu;
+pub mod display;
+pub mod gestures;
+pub mod outbox;
+pub mod probe;
+pub mod pump;
+pub mod saadc;
+#[cfg(feature = "tasks")]
+pub mod tasks;
+pub mod temperature;
+
+#[cfg(kani)]
+mod proofs;
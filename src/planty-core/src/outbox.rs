@@ -0,0 +1,67 @@
+//! Updates waiting for the BLE layer, which only gets to them between radio
+//! events and not at all while no central is connected.
+//!
+//! Most updates replace the previous one of their kind, like a new moisture
+//! reading, so only the latest of each kind is kept and nothing piles up
+//! however long they wait. Updates without a kind, like the acknowledgement
+//! of a pump command, each matter and are kept one by one.
+
+use crate::bus::{Lane, Push};
+
+pub trait Coalesce {
+    /// Updates of the same kind replace each other. `None` keeps every one.
+    /// Kinds are numbered from 0.
+    fn kind(&self) -> Option<usize>;
+}
+
+/// The latest update of each of `KINDS` kinds, and up to `N` updates without
+/// a kind.
+pub struct Outbox<T, const KINDS: usize, const N: usize> {
+    latest: [Option<T>; KINDS],
+    kept: Lane<T, N>,
+}
+
+impl<T: Coalesce + Copy + PartialEq, const KINDS: usize, const N: usize> Outbox<T, KINDS, N> {
+    pub const fn new() -> Self {
+        Self {
+            latest: [None; KINDS],
+            kept: Lane::new(),
+        }
+    }
+
+    /// Only drops an update without a kind, when `N` of them are waiting.
+    ///
+    /// # Panics
+    ///
+    /// If the update's kind is `KINDS` or above.
+    pub fn push(&mut self, update: T) -> Push {
+        match update.kind() {
+            Some(kind) => match self.latest[kind].replace(update) {
+                Some(_) => Push::Coalesced,
+                None => Push::Queued,
+            },
+            None if self.kept.push(update) => Push::Queued,
+            None => Push::Dropped,
+        }
+    }
+
+    /// Updates without a kind first, oldest first, then the latest of each
+    /// kind in the order of their kinds.
+    pub fn pop(&mut self) -> Option<T> {
+        self.kept
+            .pop()
+            .or_else(|| self.latest.iter_mut().find_map(Option::take))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kept.is_empty() && self.latest.iter().all(Option::is_none)
+    }
+}
+
+impl<T: Coalesce + Copy + PartialEq, const KINDS: usize, const N: usize> Default
+    for Outbox<T, KINDS, N>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
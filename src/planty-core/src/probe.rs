@@ -0,0 +1,158 @@
+//! Health of the moisture probe, judged from its readings alone.
+//!
+//! Every reading is classified before the controller acts on it. A reading
+//! at either rail means the wiring is broken. A reading outside what the
+//! probe can read, soil that dries faster than soil does, or a reading that
+//! never moves means the probe can't be trusted to say when to water. The
+//! usual cause is a probe that fell out of the pot and reads dry air.
+
+use crate::{
+    display::{DRY_READING, WET_READING},
+    saadc,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    Ok = 0,
+    /// Near ground: nothing drives the input, the probe's output or power
+    /// wire is disconnected.
+    Open = 1,
+    /// Near the supply: the output is tied to the supply.
+    Short = 2,
+    /// The last `Config::stuck_samples` readings didn't move.
+    Stuck = 3,
+    /// The reading rose faster than soil dries. Lasts until it comes back
+    /// down or the probe is calibrated again.
+    Jump = 4,
+    /// Outside what the probe reads between water and dry air.
+    OutOfRange = 5,
+}
+
+impl Health {
+    pub fn decode(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Health::Ok,
+            1 => Health::Open,
+            2 => Health::Short,
+            3 => Health::Stuck,
+            4 => Health::Jump,
+            5 => Health::OutOfRange,
+            _ => return None,
+        })
+    }
+
+    pub fn is_ok(self) -> bool {
+        self == Health::Ok
+    }
+
+    /// Broken wiring, as opposed to readings that are merely implausible.
+    pub fn is_electrical(self) -> bool {
+        matches!(self, Health::Open | Health::Short)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Readings below this are `Open`.
+    pub open_below: u16,
+    /// Readings this close to the supply voltage, in counts, are `Short`.
+    pub short_margin: u16,
+    /// What a working probe reads, from water to dry air.
+    pub min_reading: u16,
+    pub max_reading: u16,
+    /// The most a reading may rise from one measurement to the next.
+    pub max_rise: u16,
+    /// How many readings in a row within `stuck_tolerance` of each other
+    /// make the probe `Stuck`.
+    pub stuck_samples: u16,
+    pub stuck_tolerance: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            open_below: 64,
+            short_margin: 100,
+            min_reading: WET_READING - 200,
+            max_reading: DRY_READING + 200,
+            max_rise: 300,
+            // Five minutes at the normal measurement interval. A working
+            // probe never reads the exact same value that long.
+            stuck_samples: 30,
+            stuck_tolerance: 0,
+        }
+    }
+}
+
+/// Classifies readings, keeping what it needs of the previous ones.
+pub struct Monitor {
+    config: Config,
+    /// The last reading that wasn't implausible, which rises are measured
+    /// from. It stays put after a jump, so the jump lasts until the reading
+    /// comes back down.
+    baseline: Option<u16>,
+    /// Range and length of the current run of readings that stayed within
+    /// `stuck_tolerance`.
+    run: Option<(u16, u16)>,
+    run_len: u16,
+}
+
+impl Monitor {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            baseline: None,
+            run: None,
+            run_len: 0,
+        }
+    }
+
+    /// Forgets previous readings, for when the probe was put back in place.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Classifies `reading`, taken at a supply of `vdd_millivolts`.
+    pub fn check(&mut self, reading: u16, vdd_millivolts: u16) -> Health {
+        let config = self.config;
+        if reading < config.open_below {
+            return Health::Open;
+        }
+        if reading.saturating_add(config.short_margin) >= saadc::to_counts(vdd_millivolts) {
+            return Health::Short;
+        }
+        if !(config.min_reading..=config.max_reading).contains(&reading) {
+            return Health::OutOfRange;
+        }
+
+        let stuck = self.track_run(reading);
+        if let Some(baseline) = self.baseline {
+            if reading > baseline.saturating_add(config.max_rise) {
+                return Health::Jump;
+            }
+        }
+        self.baseline = Some(reading);
+        if stuck {
+            Health::Stuck
+        } else {
+            Health::Ok
+        }
+    }
+
+    /// Adds `reading` to the current run, or starts a new one. Returns
+    /// whether the run is long enough to call the probe stuck.
+    fn track_run(&mut self, reading: u16) -> bool {
+        let (low, high) = match self.run {
+            Some((low, high)) => (low.min(reading), high.max(reading)),
+            None => (reading, reading),
+        };
+        if high - low > self.config.stuck_tolerance {
+            self.run = Some((reading, reading));
+            self.run_len = 1;
+        } else {
+            self.run = Some((low, high));
+            self.run_len = self.run_len.saturating_add(1);
+        }
+        self.run_len >= self.config.stuck_samples
+    }
+}
@@ -0,0 +1,159 @@
+//! Kani proofs of the pump safety properties, checked on the host with
+//!
+//! ```sh
+//! cargo kani -p planty-core
+//! ```
+//!
+//! The harnesses drive `control::TABLE` with any sequence of events and guard
+//! results, carrying out the pump actions the way `control_task` does and
+//! keeping its deadline with `control::deadline_after`.
+
+use crate::control::{
+    self, step, Action, Event, State, Transition, MAX_WATERING_MS, THRESHOLD_BUFFER,
+};
+
+/// Enough events to go through every state and back.
+const STEPS: usize = 6;
+
+fn any_event() -> Event {
+    let i: usize = kani::any();
+    kani::assume(i < Event::ALL.len());
+    Event::ALL[i]
+}
+
+fn any_state() -> State {
+    let i: usize = kani::any();
+    kani::assume(i < State::ALL.len());
+    State::ALL[i]
+}
+
+fn apply(pump: &mut bool, transition: &Transition) {
+    for action in transition.actions().into_iter().flatten() {
+        match action {
+            Action::StartPump => *pump = true,
+            Action::StopPump => *pump = false,
+            Action::Measure | Action::Calibrate => {}
+        }
+    }
+}
+
+#[kani::proof]
+#[kani::unwind(25)]
+fn pump_runs_only_while_watering() {
+    let mut state = State::Idle;
+    let mut pump = false;
+
+    for _ in 0..STEPS {
+        let supply_ok: bool = kani::any();
+        if let Ok(transition) = step(state, any_event(), |_| supply_ok) {
+            apply(&mut pump, &transition);
+            state = transition.to;
+        }
+        assert_eq!(pump, state == State::Watering);
+        assert!(state != State::Fault || !pump);
+    }
+}
+
+#[kani::proof]
+#[kani::unwind(25)]
+fn pump_never_starts_on_a_low_supply() {
+    if let Ok(transition) = step(any_state(), any_event(), |_| false) {
+        assert!(!transition.actions().contains(&Some(Action::StartPump)));
+    }
+}
+
+#[kani::proof]
+#[kani::unwind(25)]
+fn faults_always_stop_the_pump() {
+    let state = any_state();
+    let mut pump = state == State::Watering;
+    if let Ok(transition) = step(state, Event::Fault, |_| kani::any()) {
+        apply(&mut pump, &transition);
+        assert_eq!(transition.to, State::Fault);
+    }
+    assert!(!pump);
+}
+
+#[kani::proof]
+fn watering_deadline_is_bounded() {
+    let now: u64 = kani::any();
+    let requested: Option<u64> = kani::any();
+    kani::assume(now <= u64::MAX - MAX_WATERING_MS);
+
+    let deadline = control::watering_deadline(now, requested);
+    assert!(deadline >= now);
+    assert!(deadline - now <= MAX_WATERING_MS);
+}
+
+/// Events arrive at any time, and once the deadline from
+/// `control::deadline_after` has passed, `control_task` handles
+/// `WateringComplete` before anything else. Taken through the table, that
+/// has to stop the pump, so it never runs past its deadline.
+#[kani::proof]
+#[kani::unwind(25)]
+fn pump_stops_at_the_deadline() {
+    let mut state = State::Idle;
+    let mut pump = false;
+    let mut now: u64 = 0;
+    let mut deadline: Option<u64> = None;
+    let mut started_at = 0;
+
+    for _ in 0..STEPS {
+        let elapsed: u64 = kani::any();
+        kani::assume(elapsed <= 2 * MAX_WATERING_MS);
+        now += elapsed;
+
+        let expired = deadline.is_some_and(|at| at <= now);
+        let event = if expired {
+            Event::WateringComplete
+        } else {
+            any_event()
+        };
+        let was_running = pump;
+        let supply_ok: bool = kani::any();
+        if let Ok(transition) = step(state, event, |_| supply_ok) {
+            apply(&mut pump, &transition);
+            deadline = control::deadline_after(&transition, now, deadline, kani::any());
+            state = transition.to;
+        }
+
+        if expired {
+            assert!(!pump);
+        }
+        if pump && !was_running {
+            started_at = now;
+        }
+        if pump {
+            let deadline = deadline.unwrap();
+            assert!(deadline - started_at <= MAX_WATERING_MS);
+        }
+    }
+}
+
+#[kani::proof]
+fn threshold_never_underflows() {
+    let dry_reading: u16 = kani::any();
+    let threshold = control::threshold(dry_reading);
+    assert!(threshold <= dry_reading);
+    if dry_reading >= THRESHOLD_BUFFER {
+        assert_eq!(dry_reading - threshold, THRESHOLD_BUFFER);
+    }
+}
+
+/// `05-watering` and `06-state-machine-watering` read the SAADC as `i16` and
+/// used to compute `dry_reading - THRESHOLD_BUFFER`, which overflows for
+/// readings near `i16::MIN`.
+#[kani::proof]
+#[kani::should_panic]
+fn unchecked_i16_threshold_overflows() {
+    let dry_reading: i16 = kani::any();
+    let _ = dry_reading - THRESHOLD_BUFFER as i16;
+}
+
+/// The saturating version they use now.
+#[kani::proof]
+fn saturating_i16_threshold_is_total() {
+    let dry_reading: i16 = kani::any();
+    let threshold = dry_reading.saturating_sub(THRESHOLD_BUFFER as i16);
+    assert!(threshold <= dry_reading);
+}
@@ -0,0 +1,130 @@
+//! Pump commands from BLE clients and buttons, and the acknowledgements sent
+//! back to BLE clients.
+//!
+//! A BLE write to the pump control characteristic gets a sequence number and
+//! is acknowledged twice on the pump status characteristic: once as `Queued`
+//! (or `QueueFull`) when the write arrives, then with the controller's
+//! verdict once it has acted on it.
+
+use crate::control::{Rejection, Transition};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Start,
+    Stop,
+}
+
+impl Command {
+    /// Decodes a pump control write: any non-zero value starts the pump.
+    pub fn decode(value: u8) -> Self {
+        if value > 0 {
+            Command::Start
+        } else {
+            Command::Stop
+        }
+    }
+
+    pub fn encode(self) -> u8 {
+        match self {
+            Command::Start => 1,
+            Command::Stop => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Waiting for the controller.
+    Queued = 0,
+    /// The pump started or stopped.
+    Done = 1,
+    /// The controller had too many commands pending, nothing happened.
+    QueueFull = 2,
+    /// The supply is too low to run the pump.
+    LowSupply = 3,
+    /// The pump was already running or already stopped.
+    Unchanged = 4,
+    /// A hardware fault keeps the pump off.
+    Fault = 5,
+}
+
+impl Outcome {
+    pub fn decode(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Outcome::Queued,
+            1 => Outcome::Done,
+            2 => Outcome::QueueFull,
+            3 => Outcome::LowSupply,
+            4 => Outcome::Unchanged,
+            5 => Outcome::Fault,
+            _ => return None,
+        })
+    }
+
+    /// Whether the controller is done with the command.
+    pub fn is_final(self) -> bool {
+        self != Outcome::Queued
+    }
+}
+
+/// What became of the command with `sequence`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ack {
+    pub sequence: u8,
+    pub command: Command,
+    pub outcome: Outcome,
+}
+
+impl Ack {
+    pub const LEN: usize = 3;
+
+    /// Sequence number, command as written, outcome.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        [self.sequence, self.command.encode(), self.outcome as u8]
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let [sequence, command, outcome] = *bytes else {
+            return None;
+        };
+        Some(Self {
+            sequence,
+            command: Command::decode(command),
+            outcome: Outcome::decode(outcome)?,
+        })
+    }
+}
+
+/// A pump command written by a BLE client, for the controller to act on and
+/// acknowledge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub sequence: u8,
+    pub command: Command,
+}
+
+impl Request {
+    pub fn ack(&self, outcome: Outcome) -> Ack {
+        Ack {
+            sequence: self.sequence,
+            command: self.command,
+            outcome,
+        }
+    }
+}
+
+impl From<Result<Transition, Rejection>> for Outcome {
+    fn from(result: Result<Transition, Rejection>) -> Self {
+        match result {
+            Ok(_) => Outcome::Done,
+            Err(Rejection::LowSupply) => Outcome::LowSupply,
+            Err(Rejection::Fault) => Outcome::Fault,
+            Err(
+                Rejection::AlreadyWatering
+                | Rejection::NotWatering
+                | Rejection::Watering
+                | Rejection::NotFaulty,
+            ) => Outcome::Unchanged,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Conversions and bookkeeping for the SAADC, as the firmware configures it:
+//! single ended, 12 bit, with the default 1/6 gain and 0.6 V internal
+//! reference.
+
+/// The reading at full scale.
+pub const FULL_SCALE_MILLIVOLTS: u32 = 3600;
+pub const FULL_SCALE_COUNTS: u32 = 1 << 12;
+
+/// The supply ratiometric readings are scaled to: two fresh AA cells, so
+/// readings on fresh cells are the same either way.
+pub const REFERENCE_MILLIVOLTS: u16 = 3000;
+
+/// How far the die temperature may drift from the last offset calibration,
+/// in degrees Celsius. The nRF52833 product specification asks for a new
+/// calibration after at least 10 °C.
+pub const RECALIBRATE_CELSIUS: i32 = 5;
+/// How often the offset is calibrated while the die temperature can't be
+/// read, long enough for it to drift that far.
+pub const RECALIBRATE_MS: u64 = 30 * 60 * 1000;
+
+/// Voltage of a raw result, which dips below zero around ground.
+pub fn to_millivolts(counts: i16) -> u16 {
+    (counts.max(0) as u32 * FULL_SCALE_MILLIVOLTS / FULL_SCALE_COUNTS) as u16
+}
+
+/// What the SAADC reads for `millivolts`.
+pub fn to_counts(millivolts: u16) -> u16 {
+    (u32::from(millivolts) * FULL_SCALE_COUNTS / FULL_SCALE_MILLIVOLTS) as u16
+}
+
+/// `reading`, taken at a supply of `vdd_millivolts`, as it would read at
+/// `REFERENCE_MILLIVOLTS`. Only meaningful for a probe whose output scales
+/// with the supply it is powered from.
+pub fn ratiometric(reading: u16, vdd_millivolts: u16) -> u16 {
+    if vdd_millivolts == 0 {
+        return reading;
+    }
+    let scaled = u32::from(reading) * u32::from(REFERENCE_MILLIVOLTS) / u32::from(vdd_millivolts);
+    scaled.min(FULL_SCALE_COUNTS - 1) as u16
+}
+
+/// Decides when the SAADC offset needs calibrating: before the first sample
+/// and whenever the die temperature drifted `RECALIBRATE_CELSIUS` from the
+/// last calibration. While the temperature can't be read, it falls back to
+/// calibrating every `RECALIBRATE_MS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OffsetCalibration {
+    /// Die temperature at the last calibration, in quarter degrees as the
+    /// TEMP peripheral reports it, if it could be read.
+    temperature: Option<i32>,
+    /// Uptime at the last calibration.
+    at_ms: Option<u64>,
+}
+
+impl OffsetCalibration {
+    pub const fn new() -> Self {
+        Self {
+            temperature: None,
+            at_ms: None,
+        }
+    }
+
+    /// Whether to calibrate at `now_ms` and `quarter_celsius`, `None` if the
+    /// temperature read failed. Assumes the caller does when told to.
+    pub fn due(&mut self, now_ms: u64, quarter_celsius: Option<i32>) -> bool {
+        let due = match (self.at_ms, self.temperature, quarter_celsius) {
+            (None, _, _) => true,
+            (_, Some(at), Some(t)) => (t - at).abs() >= RECALIBRATE_CELSIUS * 4,
+            // The first temperature since reads failed becomes the reference
+            (_, None, Some(_)) => true,
+            (Some(at_ms), _, None) => now_ms.saturating_sub(at_ms) >= RECALIBRATE_MS,
+        };
+        if due {
+            self.temperature = quarter_celsius;
+            self.at_ms = Some(now_ms);
+        }
+        due
+    }
+}
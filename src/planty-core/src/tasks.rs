@@ -0,0 +1,391 @@
+//! The task loops of `08-ble-watering`, generic over the hardware so they run
+//! on the host too. The firmware wraps each of them in an
+//! `embassy_executor::task` and implements `Plant` on the pump pin, the SAADC
+//! and the BLE server; `tests/tasks.rs` implements it on a simulated plant
+//! and drives time with embassy-time's mock driver:
+//!
+//! ```sh
+//! cargo test -p planty-core --features tasks --target x86_64-unknown-linux-gnu
+//! ```
+
+use core::{cell::RefCell, future::Future, pin::pin};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::{
+    blocking_mutex::{raw::RawMutex, Mutex},
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    battery,
+    bus::{Bus, Counters, Priority, Push},
+    control::{self, Action, Event, Guard, Rejection, Rule, State, Transition},
+    gestures::{self, Button, Gesture, Recognizer},
+    probe::{self, Health},
+    pump::Request,
+    saadc,
+    temperature::{self, Compensation},
+};
+
+/// How often a task waiting in `idle` checks in with the watchdog.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long automatic watering runs on a good supply.
+pub const WATERING_MS: u64 = 5_000;
+/// The moisture threshold until the first calibration.
+pub const DEFAULT_THRESHOLD: u16 = 2000;
+
+/// Who sent an event, and so who to tell when it is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Timer,
+    Button,
+    /// A BLE pump command, acknowledged on the pump status characteristic.
+    Ble(Request),
+}
+
+pub type Message = (Event, Source);
+
+/// The queue feeding `control`, prioritized by `bus::Bus`. Sending never
+/// waits, so buttons, timers and GATT callbacks can all use it.
+pub struct Events<M: RawMutex, const N: usize> {
+    bus: Mutex<M, RefCell<Bus<Message, N>>>,
+    ready: Signal<M, ()>,
+}
+
+impl<M: RawMutex, const N: usize> Events<M, N> {
+    pub const fn new() -> Self {
+        Self {
+            bus: Mutex::new(RefCell::new(Bus::new())),
+            ready: Signal::new(),
+        }
+    }
+
+    pub fn send(&self, priority: Priority, source: Source, event: Event) -> Push {
+        let push = self
+            .bus
+            .lock(|bus| bus.borrow_mut().push(priority, (event, source)));
+        if push == Push::Queued {
+            self.ready.signal(());
+        }
+        push
+    }
+
+    /// Waits for the most urgent pending event.
+    pub async fn receive(&self) -> Message {
+        loop {
+            if let Some(message) = self.bus.lock(|bus| bus.borrow_mut().pop()) {
+                return message;
+            }
+            self.ready.wait().await;
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.bus.lock(|bus| bus.borrow().counters())
+    }
+}
+
+impl<M: RawMutex, const N: usize> Default for Events<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Debounced level changes: the button, whether it is pressed and when it
+/// moved.
+pub type Edges<M, const N: usize> = Channel<M, (Button, bool, Instant), N>;
+
+/// Waits for `future`, calling `heartbeat` every `HEARTBEAT_INTERVAL` while it
+/// is pending.
+pub async fn idle<F: Future>(mut heartbeat: impl FnMut(), future: F) -> F::Output {
+    let mut future = pin!(future);
+    loop {
+        heartbeat();
+        if let Either::First(output) =
+            select(future.as_mut(), Timer::after(HEARTBEAT_INTERVAL)).await
+        {
+            return output;
+        }
+    }
+}
+
+/// Asks for a measurement every `interval()`.
+pub async fn measure<M: RawMutex, const N: usize>(
+    events: &Events<M, N>,
+    mut interval: impl FnMut() -> Duration,
+    mut heartbeat: impl FnMut(),
+) -> ! {
+    loop {
+        idle(&mut heartbeat, Timer::after(interval())).await;
+        events.send(Priority::Periodic, Source::Timer, Event::Measure);
+    }
+}
+
+/// Recognizes gestures in `edges` and hands them to `act`.
+pub async fn gestures<M: RawMutex, const N: usize>(
+    edges: &Edges<M, N>,
+    mut heartbeat: impl FnMut(),
+    mut act: impl FnMut(Gesture),
+) -> ! {
+    let mut recognizer = Recognizer::new(gestures::Config::default());
+
+    loop {
+        let deadline = recognizer.deadline();
+        let deadline = async move {
+            match deadline {
+                Some(at) => Timer::at(Instant::from_millis(at)).await,
+                None => core::future::pending().await,
+            }
+        };
+
+        match idle(&mut heartbeat, select(edges.receive(), deadline)).await {
+            Either::First((button, pressed, at)) => {
+                recognizer.edge(button, pressed, at.as_millis(), &mut act)
+            }
+            Either::Second(()) => recognizer.poll(Instant::now().as_millis(), &mut act),
+        }
+    }
+}
+
+/// The controller event for a gesture, if it has one.
+pub fn gesture_event(gesture: Gesture) -> Option<(Priority, Event)> {
+    match gesture {
+        Gesture::Long(Button::A) => Some((Priority::User, Event::Water)),
+        // Letting go of A stops the pump, which must not wait behind anything
+        Gesture::LongEnd(Button::A) => Some((Priority::Safety, Event::WateringComplete)),
+        Gesture::Short(Button::A) => Some((Priority::User, Event::Measure)),
+        Gesture::Long(Button::B) => Some((Priority::User, Event::Calibrate)),
+        Gesture::Short(Button::B)
+        | Gesture::LongEnd(Button::B)
+        | Gesture::Double(_)
+        | Gesture::Chord => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    /// Lower numbers indicate more moisture.
+    pub moisture: u16,
+    pub vdd_millivolts: u16,
+    /// Die temperature in quarter degrees Celsius, if it could be read.
+    pub quarter_celsius: Option<i32>,
+}
+
+/// What `control` made of a sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Measurement {
+    /// With the moisture reading scaled if the plant is `RATIOMETRIC`, and
+    /// compensated for temperature.
+    pub sample: Sample,
+    pub threshold: u16,
+    pub low_battery: bool,
+    /// The battery is low now but wasn't at the last measurement.
+    pub battery_went_low: bool,
+    pub health: Health,
+    /// `health` differs from the last measurement.
+    pub health_changed: bool,
+    /// The reading is above the threshold, and the probe can be trusted.
+    pub dry: bool,
+    /// How long to water, if the soil is dry and the supply can drive the
+    /// pump.
+    pub watering_ms: Option<u64>,
+    /// The temperature compensation the readings since the last watering
+    /// suggest, once there are enough of them.
+    pub estimate: Option<Compensation>,
+}
+
+/// Everything `control` drives.
+pub trait Plant {
+    /// The probe is powered from the supply, so its readings are scaled to
+    /// `saadc::REFERENCE_MILLIVOLTS` before they are compared to the
+    /// threshold. Probe health is always judged on the raw reading.
+    const RATIOMETRIC: bool = false;
+
+    /// Samples the moisture probe and the supply voltage.
+    fn sample(&mut self) -> impl Future<Output = Sample>;
+
+    fn set_pump(&mut self, on: bool);
+
+    /// Reports a measurement: display, BLE, alerts.
+    fn measured(&mut self, measurement: &Measurement);
+
+    fn calibrated(&mut self, reading: u16, threshold: u16);
+
+    /// Manual watering ran for `control::MAX_WATERING_MS` without being
+    /// stopped, and the pump was stopped for it.
+    fn pump_timed_out(&mut self) {}
+
+    /// The temperature compensation to apply to the next reading.
+    fn compensation(&mut self) -> Compensation {
+        Compensation::NONE
+    }
+
+    /// Tells the sender of `message` what became of it in `state`.
+    fn reply(&mut self, message: Message, state: State, result: Result<Transition, Rejection>);
+
+    /// Checks in with the watchdog while waiting for events.
+    fn heartbeat(&mut self) {}
+}
+
+/// Runs `control::TABLE`: looks up every event, checks guards, replies to the
+/// sender and carries out the actions.
+pub async fn control<M: RawMutex, const N: usize, P: Plant>(
+    events: &Events<M, N>,
+    mut plant: P,
+) -> ! {
+    let mut threshold = DEFAULT_THRESHOLD;
+    let mut state = State::Idle;
+    // When the running pump stops by itself
+    let mut deadline: Option<Instant> = None;
+    // Whether it runs until stopped, rather than for a dry measurement
+    let mut manual = false;
+    // Raised by a measurement, handled before any queued event
+    let mut follow_up: Option<Message> = None;
+    let mut watering_ms = WATERING_MS;
+    let mut low_battery = false;
+    let mut probe = probe::Monitor::new(probe::Config::default());
+    let mut health = Health::Ok;
+    let mut history = temperature::Estimator::new();
+
+    loop {
+        let expired = deadline.is_some_and(|at| at <= Instant::now());
+        let (event, source) = match follow_up.take() {
+            Some(message) => message,
+            // Before anything else queued, so a flood of events can't keep
+            // the pump running
+            None if expired => (Event::WateringComplete, Source::Timer),
+            None => {
+                // Keep handling events while watering, so a stop is never held up
+                let timeout = async move {
+                    match deadline {
+                        Some(at) => Timer::at(at).await,
+                        None => core::future::pending().await,
+                    }
+                };
+                let next = select(events.receive(), timeout);
+                match idle(|| plant.heartbeat(), next).await {
+                    Either::First(message) => message,
+                    Either::Second(()) => (Event::WateringComplete, Source::Timer),
+                }
+            }
+        };
+
+        let supply_ok = match control::rule(state, event) {
+            Rule::Go {
+                guard: Some(Guard::SupplyOk),
+                ..
+            } => battery::can_start_pump(plant.sample().await.vdd_millivolts),
+            _ => true,
+        };
+        let result = control::step(state, event, |_| supply_ok);
+        plant.reply((event, source), state, result);
+        let Ok(transition) = result else {
+            continue;
+        };
+
+        for action in transition.actions().into_iter().flatten() {
+            match action {
+                Action::StartPump => {
+                    // Watering changes the moisture, which would pass for a
+                    // change with temperature
+                    history.reset();
+                    plant.set_pump(true);
+                }
+                Action::StopPump => plant.set_pump(false),
+                Action::Measure => {
+                    let raw = plant.sample().await;
+                    let was_low = low_battery;
+                    low_battery = battery::is_low(raw.vdd_millivolts);
+                    let was = health;
+                    health = probe.check(raw.moisture, raw.vdd_millivolts);
+                    let scaled = scale::<P>(raw);
+                    if let (Some(t), true) = (raw.quarter_celsius, health.is_ok()) {
+                        history.add(t, scaled);
+                    }
+                    let sample = Sample {
+                        moisture: compensate(&mut plant, scaled, raw.quarter_celsius),
+                        ..raw
+                    };
+                    // Never water on a reading the probe can't vouch for: a
+                    // probe in dry air reads as dry as it gets
+                    let dry = health.is_ok() && sample.moisture > threshold;
+                    let watering = dry
+                        .then(|| battery::watering_ms(sample.vdd_millivolts, WATERING_MS))
+                        .flatten();
+                    plant.measured(&Measurement {
+                        sample,
+                        threshold,
+                        low_battery,
+                        battery_went_low: low_battery && !was_low,
+                        health,
+                        health_changed: health != was,
+                        dry,
+                        watering_ms: watering,
+                        estimate: history.estimate(),
+                    });
+
+                    // Broken wiring puts the controller in `Fault` until a
+                    // measurement finds it fixed
+                    let faulty = transition.to == State::Fault;
+                    if health.is_electrical() != faulty {
+                        let event = if faulty {
+                            Event::FaultCleared
+                        } else {
+                            Event::Fault
+                        };
+                        follow_up = Some((event, Source::Timer));
+                    } else if let Some(ms) = watering {
+                        watering_ms = ms;
+                        follow_up = Some((Event::SoilDry, Source::Timer));
+                    }
+                }
+                Action::Calibrate => {
+                    // The probe sits in soil that is just dry enough to water
+                    let sample = plant.sample().await;
+                    let reading =
+                        compensate(&mut plant, scale::<P>(sample), sample.quarter_celsius);
+                    threshold = control::threshold(reading);
+                    // Whatever the probe read before it was put back doesn't
+                    // count against it
+                    probe.reset();
+                    plant.calibrated(reading, threshold);
+                }
+            }
+        }
+
+        // Only the deadline stops the pump from the timer
+        if manual && (event, source) == (Event::WateringComplete, Source::Timer) {
+            plant.pump_timed_out();
+        }
+        if transition.to == State::Watering && transition.from != State::Watering {
+            manual = event != Event::SoilDry;
+        }
+        deadline = control::deadline_after(
+            &transition,
+            Instant::now().as_millis(),
+            deadline.map(|at| at.as_millis()),
+            watering_ms,
+        )
+        .map(Instant::from_millis);
+        state = transition.to;
+    }
+}
+
+/// The moisture reading, ratiometric if the plant is.
+fn scale<P: Plant>(sample: Sample) -> u16 {
+    if P::RATIOMETRIC {
+        saadc::ratiometric(sample.moisture, sample.vdd_millivolts)
+    } else {
+        sample.moisture
+    }
+}
+
+/// `reading` as it would read at `temperature::REFERENCE_CELSIUS`.
+fn compensate(plant: &mut impl Plant, reading: u16, quarter_celsius: Option<i32>) -> u16 {
+    match quarter_celsius {
+        Some(t) => plant.compensation().apply(reading, t),
+        None => reading,
+    }
+}
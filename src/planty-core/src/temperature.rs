@@ -0,0 +1,122 @@
+//! Temperature compensation of moisture readings. A capacitive probe reads
+//! differently in cold soil than in warm soil of the same moisture, so
+//! without compensation the plant gets watered more on cold mornings.
+//!
+//! Temperatures are in quarter degrees Celsius, as the nRF52 TEMP peripheral
+//! reports them. The compensation is linear around `REFERENCE_CELSIUS`, and
+//! `Estimator` fits its coefficient to readings taken while the soil moisture
+//! stayed put, i.e. between two waterings.
+
+/// Compensated readings are what the probe would read at this temperature.
+pub const REFERENCE_CELSIUS: i32 = 20;
+
+/// What `Estimator` needs before it commits to a coefficient: an hour of
+/// readings at the normal measurement interval, spread over a few degrees.
+pub const MIN_HISTORY: u32 = 360;
+pub const MIN_SPREAD_CELSIUS: i32 = 3;
+
+/// How an estimate that isn't there yet is sent over BLE.
+pub const NO_ESTIMATE: i16 = i16::MIN;
+
+/// The largest reading the 12 bit SAADC returns.
+const MAX_READING: i64 = (1 << 12) - 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Compensation {
+    /// How much the reading rises per degree Celsius, in hundredths of a
+    /// count.
+    pub centicounts_per_celsius: i16,
+}
+
+impl Compensation {
+    pub const NONE: Self = Self {
+        centicounts_per_celsius: 0,
+    };
+
+    /// `reading`, taken at `quarter_celsius`, as it would read at
+    /// `REFERENCE_CELSIUS`.
+    pub fn apply(self, reading: u16, quarter_celsius: i32) -> u16 {
+        let quarters = i64::from(quarter_celsius - REFERENCE_CELSIUS * 4);
+        // Hundredths of a count per degree, times quarter degrees
+        let offset = i64::from(self.centicounts_per_celsius) * quarters / 400;
+        (i64::from(reading) - offset).clamp(0, MAX_READING) as u16
+    }
+}
+
+/// A least squares fit of readings against temperature, kept as running sums
+/// so it needs no history buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Estimator {
+    count: u32,
+    sum_t: i64,
+    sum_r: i64,
+    sum_tt: i64,
+    sum_tr: i64,
+    coldest: i32,
+    warmest: i32,
+}
+
+impl Estimator {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            sum_t: 0,
+            sum_r: 0,
+            sum_tt: 0,
+            sum_tr: 0,
+            coldest: i32::MAX,
+            warmest: i32::MIN,
+        }
+    }
+
+    /// Starts over, for when the soil moisture changed.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Adds an uncompensated reading taken at `quarter_celsius`.
+    pub fn add(&mut self, quarter_celsius: i32, reading: u16) {
+        let (t, r) = (i64::from(quarter_celsius), i64::from(reading));
+        self.count = self.count.saturating_add(1);
+        self.sum_t += t;
+        self.sum_r += r;
+        self.sum_tt += t * t;
+        self.sum_tr += t * r;
+        self.coldest = self.coldest.min(quarter_celsius);
+        self.warmest = self.warmest.max(quarter_celsius);
+    }
+
+    /// The coefficient that best explains the readings so far, once there
+    /// are enough of them over a wide enough range of temperatures.
+    pub fn estimate(&self) -> Option<Compensation> {
+        if self.count < MIN_HISTORY || self.warmest - self.coldest < MIN_SPREAD_CELSIUS * 4 {
+            return None;
+        }
+        // Wide enough for weeks of history between two waterings
+        let n = i128::from(self.count);
+        let (sum_t, sum_r) = (i128::from(self.sum_t), i128::from(self.sum_r));
+        let covariance = n * i128::from(self.sum_tr) - sum_t * sum_r;
+        let variance = n * i128::from(self.sum_tt) - sum_t * sum_t;
+        // Counts per quarter degree, in hundredths of a count per degree
+        let slope = covariance * 400 / variance;
+        let centicounts_per_celsius = i16::try_from(slope)
+            .ok()
+            .filter(|&coefficient| coefficient != NO_ESTIMATE)?;
+        Some(Compensation {
+            centicounts_per_celsius,
+        })
+    }
+}
+
+/// An estimate as the compensation estimate characteristic holds it.
+pub fn encode_estimate(estimate: Option<Compensation>) -> i16 {
+    estimate.map_or(NO_ESTIMATE, |compensation| {
+        compensation.centicounts_per_celsius
+    })
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
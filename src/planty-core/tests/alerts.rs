@@ -0,0 +1,105 @@
+use planty_core::alerts::{
+    duration_ms, Alert, QuietHours, Settings, MAX_FREQUENCY_HZ, MAX_MELODY_MS, MIN_FREQUENCY_HZ,
+};
+
+#[test]
+fn melodies_are_playable() {
+    for alert in Alert::ALL {
+        let melody = alert.melody();
+        assert!(!melody.is_empty(), "{alert:?} is silent");
+        assert!(
+            duration_ms(melody) <= MAX_MELODY_MS,
+            "{alert:?} is too long"
+        );
+        for tone in melody {
+            assert!(tone.duration_ms > 0, "{alert:?} has an empty tone");
+            assert!(
+                tone.frequency_hz == 0
+                    || (MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ).contains(&tone.frequency_hz),
+                "{alert:?} plays {} Hz",
+                tone.frequency_hz
+            );
+        }
+        assert_ne!(melody[0].frequency_hz, 0, "{alert:?} starts with a rest");
+        assert_ne!(
+            melody[melody.len() - 1].frequency_hz,
+            0,
+            "{alert:?} ends with a rest"
+        );
+    }
+}
+
+#[test]
+fn melodies_are_distinct() {
+    for (i, a) in Alert::ALL.iter().enumerate() {
+        for b in &Alert::ALL[i + 1..] {
+            assert_ne!(a.melody(), b.melody(), "{a:?} sounds like {b:?}");
+        }
+    }
+}
+
+#[test]
+fn quiet_hours_within_a_day() {
+    let lunch = QuietHours {
+        start: 12 * 60,
+        end: 13 * 60,
+    };
+    assert!(!lunch.contains(12 * 60 - 1));
+    assert!(lunch.contains(12 * 60));
+    assert!(lunch.contains(13 * 60 - 1));
+    assert!(!lunch.contains(13 * 60));
+}
+
+#[test]
+fn quiet_hours_past_midnight() {
+    let night = QuietHours {
+        start: 22 * 60,
+        end: 7 * 60,
+    };
+    assert!(night.contains(23 * 60));
+    assert!(night.contains(0));
+    assert!(night.contains(7 * 60 - 1));
+    assert!(!night.contains(7 * 60));
+    assert!(!night.contains(12 * 60));
+    assert!(!night.contains(22 * 60 - 1));
+}
+
+#[test]
+fn empty_quiet_hours() {
+    let none = QuietHours::default();
+    assert!((0..24 * 60).all(|minute| !none.contains(minute)));
+}
+
+#[test]
+fn settings_round_trip() {
+    let settings = Settings {
+        muted: true,
+        quiet_hours: QuietHours {
+            start: 22 * 60,
+            end: 7 * 60,
+        },
+    };
+    assert_eq!(Settings::decode(&settings.encode()), Some(settings));
+    assert_eq!(Settings::decode(&[0, 0xa0, 0x05, 0, 0]), None);
+}
+
+#[test]
+fn settings_allow_alerts() {
+    let night = Settings {
+        muted: false,
+        quiet_hours: QuietHours {
+            start: 22 * 60,
+            end: 7 * 60,
+        },
+    };
+    assert!(night.allows(None));
+    assert!(night.allows(Some(12 * 60)));
+    assert!(!night.allows(Some(23 * 60)));
+
+    let muted = Settings {
+        muted: true,
+        ..night
+    };
+    assert!(!muted.allows(None));
+    assert!(!muted.allows(Some(12 * 60)));
+}
@@ -0,0 +1,19 @@
+use planty_core::battery::{
+    can_start_pump, percent, watering_ms, MIN_PUMP_MILLIVOLTS, SHORT_WATERING_MS,
+};
+
+#[test]
+fn watering_shortens_and_then_stops_as_the_supply_drops() {
+    assert_eq!(watering_ms(3000, 5_000), Some(5_000));
+    assert_eq!(watering_ms(2400, 5_000), Some(SHORT_WATERING_MS));
+    assert_eq!(watering_ms(2400, 1_000), Some(1_000));
+    assert_eq!(watering_ms(MIN_PUMP_MILLIVOLTS - 1, 5_000), None);
+    assert!(!can_start_pump(MIN_PUMP_MILLIVOLTS - 1));
+}
+
+#[test]
+fn percent_is_clamped() {
+    assert_eq!(percent(1500), 0);
+    assert_eq!(percent(2500), 50);
+    assert_eq!(percent(3300), 100);
+}
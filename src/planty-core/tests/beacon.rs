@@ -0,0 +1,83 @@
+use planty_core::beacon::{
+    DecodeError, Status, FLAG_LOW_BATTERY, FLAG_PROBE_FAULT, FLAG_WATERING, PAYLOAD_LEN,
+};
+
+/// Flags, length, the name "planty" and the status, as the firmware
+/// advertises it.
+const ADVERTISEMENT: [u8; 22] = [
+    0x02, 0x01, 0x06, // flags
+    0x07, 0x09, b'p', b'l', b'a', b'n', b't', b'y', // complete local name
+    0x0a, 0xff, 0xff, 0xff, 0x01, 0xd0, 0x07, 0x6c, 0x07, 0x05, 0x2a, // status
+];
+
+#[test]
+fn encodes_the_documented_layout() {
+    let status = Status {
+        moisture: 2000,
+        threshold: 1900,
+        flags: FLAG_WATERING | FLAG_PROBE_FAULT,
+        battery_percent: Some(42),
+    };
+    assert_eq!(status.encode(), ADVERTISEMENT[13..]);
+}
+
+#[test]
+fn decodes_a_captured_advertisement() {
+    let status = Status::decode_advertisement(&ADVERTISEMENT).unwrap();
+    assert_eq!(status.moisture, 2000);
+    assert_eq!(status.threshold, 1900);
+    assert!(status.is_watering());
+    assert!(!status.is_low_battery());
+    assert!(status.is_probe_fault());
+    assert_eq!(status.battery_percent, Some(42));
+}
+
+#[test]
+fn round_trips() {
+    for status in [
+        Status::default(),
+        Status {
+            moisture: u16::MAX,
+            threshold: 1,
+            flags: FLAG_LOW_BATTERY,
+            battery_percent: None,
+        },
+        Status {
+            moisture: 1180,
+            threshold: 2840,
+            flags: 0xff,
+            battery_percent: Some(100),
+        },
+    ] {
+        assert_eq!(Status::decode(&status.encode()), Ok(status));
+    }
+}
+
+#[test]
+fn rejects_foreign_and_malformed_payloads() {
+    let mut payload = Status::default().encode();
+    assert_eq!(
+        Status::decode(&payload[..PAYLOAD_LEN - 1]),
+        Err(DecodeError::WrongLength(PAYLOAD_LEN - 1))
+    );
+    payload[2] = 2;
+    assert_eq!(
+        Status::decode(&payload),
+        Err(DecodeError::UnsupportedVersion(2))
+    );
+    payload[0] = 0x59;
+    payload[1] = 0x00;
+    assert_eq!(
+        Status::decode(&payload),
+        Err(DecodeError::ForeignCompany(0x0059))
+    );
+
+    assert_eq!(
+        Status::decode_advertisement(&ADVERTISEMENT[..11]),
+        Err(DecodeError::Missing)
+    );
+    assert_eq!(
+        Status::decode_advertisement(&ADVERTISEMENT[..20]),
+        Err(DecodeError::Truncated)
+    );
+}
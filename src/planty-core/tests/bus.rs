@@ -0,0 +1,94 @@
+use planty_core::bus::{Bus, Counters, Priority, Push};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Event {
+    Stop,
+    Water(u8),
+    Measure,
+}
+
+fn drain(bus: &mut Bus<Event, 3>) -> Vec<Event> {
+    std::iter::from_fn(|| bus.pop()).collect()
+}
+
+#[test]
+fn higher_priorities_go_first() {
+    let mut bus = Bus::<Event, 3>::new();
+    bus.push(Priority::Periodic, Event::Measure);
+    bus.push(Priority::User, Event::Water(1));
+    bus.push(Priority::Safety, Event::Stop);
+
+    assert_eq!(
+        drain(&mut bus),
+        [Event::Stop, Event::Water(1), Event::Measure]
+    );
+    assert!(bus.is_empty());
+}
+
+#[test]
+fn same_priority_is_first_in_first_out() {
+    let mut bus = Bus::<Event, 3>::new();
+    for i in 0..3 {
+        bus.push(Priority::User, Event::Water(i));
+    }
+    assert_eq!(bus.pop(), Some(Event::Water(0)));
+    bus.push(Priority::User, Event::Water(3));
+
+    assert_eq!(
+        drain(&mut bus),
+        [Event::Water(1), Event::Water(2), Event::Water(3)]
+    );
+}
+
+#[test]
+fn periodic_ticks_coalesce() {
+    let mut bus = Bus::<Event, 3>::new();
+    assert_eq!(bus.push(Priority::Periodic, Event::Measure), Push::Queued);
+    for _ in 0..10 {
+        assert_eq!(
+            bus.push(Priority::Periodic, Event::Measure),
+            Push::Coalesced
+        );
+    }
+
+    assert_eq!(drain(&mut bus), [Event::Measure]);
+    assert_eq!(bus.counters().coalesced, 10);
+    assert_eq!(bus.push(Priority::Periodic, Event::Measure), Push::Queued);
+}
+
+#[test]
+fn user_commands_are_kept_one_by_one_and_dropped_when_full() {
+    let mut bus = Bus::<Event, 3>::new();
+    for _ in 0..3 {
+        assert_eq!(bus.push(Priority::User, Event::Water(1)), Push::Queued);
+    }
+    assert_eq!(bus.push(Priority::User, Event::Water(1)), Push::Dropped);
+
+    assert_eq!(drain(&mut bus).len(), 3);
+    assert_eq!(
+        bus.counters(),
+        Counters {
+            dropped: [0, 1, 0],
+            coalesced: 0
+        }
+    );
+}
+
+#[test]
+fn a_full_lane_does_not_hold_up_others() {
+    let mut bus = Bus::<Event, 3>::new();
+    for i in 0..5 {
+        bus.push(Priority::User, Event::Water(i));
+    }
+    assert_eq!(bus.push(Priority::Safety, Event::Stop), Push::Queued);
+    assert_eq!(bus.pop(), Some(Event::Stop));
+}
+
+#[test]
+fn counters_encode_as_little_endian() {
+    let counters = Counters {
+        dropped: [1, 0x0203, 0],
+        coalesced: 0xfffe,
+    };
+    assert_eq!(counters.encode(), [1, 0, 3, 2, 0, 0, 0xfe, 0xff]);
+}
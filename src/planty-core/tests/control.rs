@@ -0,0 +1,122 @@
+use std::{env, fs};
+
+use planty_core::control::{
+    deadline_after, diagram, rule, step, Action, Event, Guard, Rejection, Rule, State, Transition,
+    MAX_WATERING_MS, TABLE,
+};
+
+#[test]
+fn every_state_and_event_has_exactly_one_row() {
+    for state in State::ALL {
+        for event in Event::ALL {
+            let rows = TABLE
+                .iter()
+                .filter(|(from, on, _)| *from == state && *on == event)
+                .count();
+            assert_eq!(rows, 1, "{state:?} on {event:?}");
+        }
+    }
+    assert_eq!(TABLE.len(), State::ALL.len() * Event::ALL.len());
+}
+
+#[test]
+fn watering_needs_a_good_supply() {
+    assert_eq!(
+        step(State::Idle, Event::Water, |guard| guard != Guard::SupplyOk),
+        Err(Rejection::LowSupply)
+    );
+    let transition = step(State::Idle, Event::Water, |_| true).unwrap();
+    assert_eq!(transition.to, State::Watering);
+    assert_eq!(transition.actions(), [None, None, Some(Action::StartPump)]);
+}
+
+#[test]
+fn leaving_watering_stops_the_pump() {
+    let transition = step(State::Watering, Event::WateringComplete, |_| true).unwrap();
+    assert_eq!(transition.to, State::Idle);
+    assert_eq!(transition.actions(), [Some(Action::StopPump), None, None]);
+}
+
+#[test]
+fn staying_in_a_state_runs_only_the_action() {
+    assert_eq!(
+        step(State::Idle, Event::Measure, |_| true),
+        Ok(Transition {
+            from: State::Idle,
+            event: Event::Measure,
+            to: State::Idle,
+            action: Some(Action::Measure),
+        })
+    );
+}
+
+#[test]
+fn busy_pump_rejects_measuring_and_calibrating() {
+    for event in [Event::Measure, Event::Calibrate] {
+        assert_eq!(
+            rule(State::Watering, event),
+            Rule::Reject(Rejection::Watering)
+        );
+    }
+}
+
+#[test]
+fn only_transitions_into_watering_start_the_pump() {
+    for &(from, event, _) in TABLE {
+        if let Ok(transition) = step(from, event, |_| true) {
+            let starts = transition.actions().contains(&Some(Action::StartPump));
+            assert_eq!(
+                starts,
+                from == State::Idle && transition.to == State::Watering,
+                "{from:?} on {event:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn only_entering_watering_sets_a_deadline() {
+    let go = |from, event| step(from, event, |_| true).unwrap();
+
+    let dry = go(State::Idle, Event::SoilDry);
+    assert_eq!(deadline_after(&dry, 1_000, None, 5_000), Some(6_000));
+    let manual = go(State::Idle, Event::Water);
+    assert_eq!(
+        deadline_after(&manual, 1_000, None, 5_000),
+        Some(1_000 + MAX_WATERING_MS)
+    );
+    // Staying in a state keeps whatever there was
+    let measure = go(State::Idle, Event::Measure);
+    assert_eq!(
+        deadline_after(&measure, 1_000, Some(6_000), 5_000),
+        Some(6_000)
+    );
+    for event in [Event::WateringComplete, Event::Fault] {
+        let stop = go(State::Watering, event);
+        assert_eq!(deadline_after(&stop, 1_000, Some(6_000), 5_000), None);
+    }
+}
+
+/// `states.md` embeds the diagram generated from the table. Run with
+/// `UPDATE_STATES=1` to rewrite it after changing the table.
+#[test]
+fn state_diagram_is_up_to_date() {
+    let mut generated = String::new();
+    diagram(&mut generated).unwrap();
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/states.md");
+    let document = fs::read_to_string(path).unwrap();
+    let start = document.find("```mermaid\n").unwrap() + "```mermaid\n".len();
+    let end = start + document[start..].find("```").unwrap();
+
+    if env::var_os("UPDATE_STATES").is_some() {
+        let updated = format!("{}{}{}", &document[..start], generated, &document[end..]);
+        fs::write(path, updated).unwrap();
+    } else {
+        assert_eq!(
+            document[start..end],
+            generated,
+            "states.md is out of date, run the tests with UPDATE_STATES=1"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use planty_core::crash::{crc32, DecodeError, Record, ENCODED_LEN, MESSAGE_LEN, SLOT_LEN};
+
+fn record() -> Record {
+    Record::new(
+        1234,
+        "src/main.rs",
+        42,
+        format_args!("moisture {} out of range", 5000),
+    )
+}
+
+#[test]
+fn records_encode_as_documented() {
+    let (encoded, len) = record().encode();
+
+    let mut expected = vec![0xd2, 0x04, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 11];
+    expected.extend_from_slice(b"src/main.rs");
+    expected.push(26);
+    expected.extend_from_slice(b"moisture 5000 out of range");
+    assert_eq!(&encoded[..len], expected);
+}
+
+#[test]
+fn records_round_trip() {
+    let record = record();
+    let (encoded, len) = record.encode();
+
+    assert_eq!(Record::decode(&encoded[..len]), Ok(record));
+    assert_eq!(Record::load(&record.store()), Some(record));
+}
+
+#[test]
+fn long_messages_are_cut_at_a_character_boundary() {
+    let record = Record::new(0, "", 0, format_args!("{}", "é".repeat(MESSAGE_LEN)));
+
+    assert_eq!(record.message(), "é".repeat(MESSAGE_LEN / 2));
+    let (_, len) = record.encode();
+    assert!(len <= ENCODED_LEN);
+}
+
+#[test]
+fn truncated_values_are_rejected() {
+    let (encoded, len) = record().encode();
+    for cut in [0, 8, 12, 13, len - 1] {
+        assert_eq!(
+            Record::decode(&encoded[..cut]),
+            Err(DecodeError::Truncated),
+            "{cut} bytes"
+        );
+    }
+}
+
+#[test]
+fn ram_after_a_power_cycle_is_not_a_record() {
+    assert_eq!(Record::load(&[0; SLOT_LEN]), None);
+    assert_eq!(Record::load(&[0xff; SLOT_LEN]), None);
+    let mut noise = [0; SLOT_LEN];
+    let mut state = 0x1234_5678u32;
+    for byte in &mut noise {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        *byte = (state >> 24) as u8;
+    }
+    assert_eq!(Record::load(&noise), None);
+}
+
+#[test]
+fn a_corrupted_or_cleared_record_is_not_loaded() {
+    let stored = record().store();
+
+    let mut flipped = stored;
+    flipped[20] ^= 1;
+    assert_eq!(Record::load(&flipped), None);
+
+    let mut too_long = stored;
+    too_long[8..10].copy_from_slice(&u16::MAX.to_le_bytes());
+    assert_eq!(Record::load(&too_long), None);
+
+    // What clearing the record does
+    let mut cleared = stored;
+    cleared[0..4].fill(0);
+    assert_eq!(Record::load(&cleared), None);
+}
+
+#[test]
+fn crc32_matches_the_standard_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn records_display_for_the_cli() {
+    assert_eq!(
+        record().to_string(),
+        "panicked after 1.2 s at src/main.rs:42: moisture 5000 out of range"
+    );
+}
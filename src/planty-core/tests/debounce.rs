@@ -0,0 +1,200 @@
+use planty_core::debounce::{Debouncer, Edge, Strategy};
+use proptest::{prelude::*, strategy::Strategy as _};
+
+const STABLE: Strategy = Strategy::Stable { stable_ms: 20 };
+const LOCKOUT: Strategy = Strategy::Lockout { lockout_ms: 30 };
+const INTEGRATOR: Strategy = Strategy::Integrator {
+    sample_ms: 5,
+    samples: 5,
+};
+
+/// Longest contact bounce the traces contain.
+const MAX_BOUNCE_MS: u64 = 8;
+
+/// Plays raw `(ms, high)` changes the way the firmware does, polling at every
+/// deadline, and returns the edges with the time they were reported.
+fn run(strategy: Strategy, changes: &[(u64, bool)]) -> Vec<(u64, Edge)> {
+    let mut debouncer = Debouncer::new(strategy, false);
+    let mut edges = Vec::new();
+
+    for &(at, high) in changes {
+        drain(&mut debouncer, &mut edges, at);
+        if let Some(edge) = debouncer.input(high, at) {
+            edges.push((at, edge));
+        }
+    }
+    drain(&mut debouncer, &mut edges, u64::MAX);
+    assert_eq!(debouncer.deadline(), None);
+    edges
+}
+
+fn drain(debouncer: &mut Debouncer, edges: &mut Vec<(u64, Edge)>, now: u64) {
+    while let Some(deadline) = debouncer.deadline().filter(|&deadline| deadline <= now) {
+        if let Some(edge) = debouncer.poll(deadline) {
+            edges.push((deadline, edge));
+        }
+    }
+}
+
+fn edge(high: bool, at: u64) -> Edge {
+    Edge { high, at }
+}
+
+/// One press or release: how long the new level is held, the bounce right
+/// after the change as pairs of (back, forth) durations, and an optional
+/// glitch somewhere in the middle as (start per mille of the hold, length).
+type Segment = (u64, Vec<(u64, u64)>, Option<(u64, u64)>);
+
+fn segments(glitches: bool) -> impl proptest::strategy::Strategy<Value = Vec<Segment>> {
+    let glitch = if glitches {
+        proptest::option::of((0..=1000u64, 1..=5u64)).boxed()
+    } else {
+        Just(None).boxed()
+    };
+    proptest::collection::vec(
+        (
+            100..400u64,
+            proptest::collection::vec((1..=2u64, 1..=2u64), 0..=2),
+            glitch,
+        ),
+        1..12,
+    )
+}
+
+/// Turns segments into raw changes, and the true edges they stand for.
+fn trace(segments: &[Segment]) -> (Vec<(u64, bool)>, Vec<Edge>) {
+    let mut changes = Vec::new();
+    let mut edges = Vec::new();
+    let mut at = 0;
+    let mut high = false;
+
+    for (hold, bounce, glitch) in segments {
+        high = !high;
+        edges.push(edge(high, at));
+        changes.push((at, high));
+
+        let mut t = at;
+        for &(back, forth) in bounce {
+            t += back;
+            changes.push((t, !high));
+            t += forth;
+            changes.push((t, high));
+        }
+        assert!(t - at <= MAX_BOUNCE_MS);
+
+        // Well clear of the bounce on both sides
+        if let Some((start, length)) = glitch {
+            let start = at + 60 + (hold - 90) * start / 1000;
+            changes.push((start, !high));
+            changes.push((start + length, high));
+        }
+        at += hold;
+    }
+    (changes, edges)
+}
+
+fn reported(edges: &[(u64, Edge)]) -> Vec<Edge> {
+    edges.iter().map(|&(_, edge)| edge).collect()
+}
+
+/// Every true edge is reported once, stamped no later than `stamp` after it
+/// happened, and reported no later than `latency` after it happened.
+fn check_edges(
+    strategy: Strategy,
+    segments: &[Segment],
+    stamp: u64,
+    latency: u64,
+) -> Result<(), TestCaseError> {
+    let (changes, expected) = trace(segments);
+    let edges = run(strategy, &changes);
+
+    prop_assert_eq!(edges.len(), expected.len(), "{:?}", edges);
+    for ((reported_at, edge), expected) in edges.into_iter().zip(expected) {
+        prop_assert_eq!(edge.high, expected.high);
+        prop_assert!(edge.at >= expected.at && edge.at - expected.at <= stamp);
+        prop_assert!(reported_at >= edge.at && reported_at - expected.at <= latency);
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn stable_reports_every_edge_and_rejects_glitches(segments in segments(true)) {
+        check_edges(STABLE, &segments, 0, MAX_BOUNCE_MS + 20)?;
+    }
+
+    #[test]
+    fn integrator_reports_every_edge_and_rejects_glitches(segments in segments(true)) {
+        // Only sees the input when sampling, so may miss the first bounces
+        check_edges(INTEGRATOR, &segments, MAX_BOUNCE_MS, MAX_BOUNCE_MS + 6 * 5)?;
+    }
+
+    #[test]
+    fn lockout_reports_every_edge_immediately(segments in segments(false)) {
+        check_edges(LOCKOUT, &segments, 0, 0)?;
+    }
+
+    #[test]
+    fn edges_alternate_and_end_at_the_input_level(
+        changes in proptest::collection::vec((0..50u64, any::<bool>()), 0..64),
+        strategy in prop_oneof![Just(STABLE), Just(LOCKOUT), Just(INTEGRATOR)],
+    ) {
+        // Arbitrary noise, including repeated levels and simultaneous changes
+        let mut at = 0;
+        let changes: Vec<_> = changes
+            .into_iter()
+            .map(|(delay, high)| {
+                at += delay;
+                (at, high)
+            })
+            .collect();
+        let edges = run(strategy, &changes);
+
+        let mut level = false;
+        let mut last_at = 0;
+        for (_, edge) in &edges {
+            prop_assert_ne!(edge.high, level);
+            prop_assert!(edge.at >= last_at);
+            level = edge.high;
+            last_at = edge.at;
+        }
+        prop_assert_eq!(level, changes.last().is_some_and(|&(_, high)| high));
+    }
+}
+
+#[test]
+fn bounce_that_ends_at_the_old_level_still_counts() {
+    // A press whose bounce outlasts a plain debounce delay, then a release
+    let changes = [(0, true), (3, false), (4, true), (200, false)];
+    for strategy in [STABLE, LOCKOUT, INTEGRATOR] {
+        assert_eq!(
+            reported(&run(strategy, &changes)),
+            [edge(true, 0), edge(false, 200)]
+        );
+    }
+}
+
+#[test]
+fn stable_waits_for_the_input_to_settle() {
+    assert_eq!(
+        run(STABLE, &[(0, true), (5, false), (7, true)]),
+        [(27, edge(true, 0))]
+    );
+}
+
+#[test]
+fn lockout_passes_glitches() {
+    assert_eq!(
+        run(LOCKOUT, &[(0, true), (1, false)]),
+        [(0, edge(true, 0)), (30, edge(false, 1))]
+    );
+}
+
+#[test]
+fn integrator_rides_out_short_dropouts() {
+    // Sampled high at 5, 10 and 20 through 35, low at 15
+    assert_eq!(
+        run(INTEGRATOR, &[(0, true), (12, false), (17, true)]),
+        [(35, edge(true, 0))]
+    );
+}
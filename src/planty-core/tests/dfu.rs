@@ -0,0 +1,276 @@
+use std::{cell::RefCell, rc::Rc};
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use futures::executor::block_on;
+use planty_core::dfu::{Bootloader, Error, ImageWriter, Phase, MAX_CHUNK_LEN, SIGNATURE_LEN};
+use sha2::{Digest, Sha512};
+
+const PAGE: usize = 256;
+/// Four pages of image and the spare one the bootloader swaps through.
+const CAPACITY: usize = 5 * PAGE;
+
+/// A DFU partition in memory that, like NOR flash, only writes aligned words
+/// into erased memory. Clones share the same memory.
+#[derive(Clone)]
+struct MemFlash(Rc<RefCell<Vec<u8>>>);
+
+impl MemFlash {
+    fn new() -> Self {
+        Self(Rc::new(RefCell::new(vec![0; CAPACITY])))
+    }
+
+    fn contents(&self, len: usize) -> Vec<u8> {
+        self.0.borrow()[..len].to_vec()
+    }
+}
+
+impl ErrorType for MemFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MemFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        let memory = self.0.borrow();
+        let source = memory
+            .get(offset..offset + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        bytes.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        CAPACITY
+    }
+}
+
+impl NorFlash for MemFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = PAGE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let (from, to) = (from as usize, to as usize);
+        if !from.is_multiple_of(PAGE) || !to.is_multiple_of(PAGE) {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        let mut memory = self.0.borrow_mut();
+        memory
+            .get_mut(from..to)
+            .ok_or(NorFlashErrorKind::OutOfBounds)?
+            .fill(0xff);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        let aligned = |n: usize| n.is_multiple_of(Self::WRITE_SIZE);
+        if !aligned(offset) || !aligned(bytes.len()) {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        let mut memory = self.0.borrow_mut();
+        let target = memory
+            .get_mut(offset..offset + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        assert!(
+            target.iter().all(|&byte| byte == 0xff),
+            "write at {offset} into memory that isn't erased"
+        );
+        target.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Remembers whether the image was handed over.
+#[derive(Default)]
+struct Boot {
+    marked: bool,
+}
+
+impl Bootloader for Boot {
+    async fn mark_updated(&mut self, len: u32, _: &[u8; SIGNATURE_LEN]) -> Result<(), Error> {
+        assert_eq!(len, image().len() as u32);
+        self.marked = true;
+        Ok(())
+    }
+}
+
+/// Checks the signature again, and finds it doesn't match after all.
+struct Refusing;
+
+impl Bootloader for Refusing {
+    async fn mark_updated(&mut self, _: u32, _: &[u8; SIGNATURE_LEN]) -> Result<(), Error> {
+        Err(Error::BadSignature)
+    }
+}
+
+fn key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+fn public(seed: u8) -> VerifyingKey {
+    key(seed).verifying_key()
+}
+
+fn sign(key: &SigningKey, image: &[u8]) -> [u8; SIGNATURE_LEN] {
+    key.sign(&Sha512::digest(image)).to_bytes()
+}
+
+/// An image whose last chunk is not a multiple of the write size.
+fn image() -> Vec<u8> {
+    (0..1001).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+/// Starts an upload of `image` signed with `signature` and writes all of it.
+fn upload(writer: &mut ImageWriter<MemFlash>, image: &[u8], signature: [u8; SIGNATURE_LEN]) {
+    block_on(writer.start(image.len() as u32, signature)).unwrap();
+    for (i, chunk) in image.chunks(MAX_CHUNK_LEN).enumerate() {
+        let offset = (i * MAX_CHUNK_LEN) as u32;
+        block_on(writer.write(offset, chunk)).unwrap();
+    }
+}
+
+#[test]
+fn chunks_in_order_are_verified_and_handed_over() {
+    let flash = MemFlash::new();
+    let mut writer = ImageWriter::new(flash.clone());
+    let mut boot = Boot::default();
+    let image = image();
+
+    upload(&mut writer, &image, sign(&key(1), &image));
+    let status = writer.status(Error::None);
+    assert_eq!(status.phase, Phase::Receiving);
+    assert_eq!(status.next_offset, image.len() as u32);
+
+    block_on(writer.finish(&public(1), &mut boot)).unwrap();
+    assert_eq!(writer.status(Error::None).phase, Phase::Verified);
+    assert!(boot.marked);
+    assert_eq!(flash.contents(image.len()), image);
+    // The tail of the last word is left erased
+    assert_eq!(flash.contents(1004)[1001..], [0xff; 3]);
+}
+
+#[test]
+fn chunks_out_of_order_or_repeated_are_rejected() {
+    let mut writer = ImageWriter::new(MemFlash::new());
+    let image = image();
+    block_on(writer.start(image.len() as u32, sign(&key(1), &image))).unwrap();
+    block_on(writer.write(0, &image[..MAX_CHUNK_LEN])).unwrap();
+
+    let again = writer.write(0, &image[..MAX_CHUNK_LEN]);
+    assert_eq!(block_on(again), Err(Error::UnexpectedOffset));
+    let skipped = 2 * MAX_CHUNK_LEN;
+    let ahead = writer.write(skipped as u32, &image[skipped..skipped + MAX_CHUNK_LEN]);
+    assert_eq!(block_on(ahead), Err(Error::UnexpectedOffset));
+
+    // Nothing was written, so the client resumes where it left off
+    assert_eq!(writer.status(Error::None).next_offset, MAX_CHUNK_LEN as u32);
+    let next = writer.write(
+        MAX_CHUNK_LEN as u32,
+        &image[MAX_CHUNK_LEN..2 * MAX_CHUNK_LEN],
+    );
+    assert_eq!(block_on(next), Ok(()));
+}
+
+#[test]
+fn only_the_last_chunk_may_be_unaligned() {
+    let mut writer = ImageWriter::new(MemFlash::new());
+    let image = image();
+    block_on(writer.start(image.len() as u32, sign(&key(1), &image))).unwrap();
+
+    assert_eq!(
+        block_on(writer.write(0, &image[..5])),
+        Err(Error::Misaligned)
+    );
+}
+
+#[test]
+fn images_past_the_dfu_partition_are_rejected() {
+    let mut writer = ImageWriter::new(MemFlash::new());
+    let max = writer.max_image_len();
+    assert_eq!(max, (CAPACITY - PAGE) as u32);
+
+    let signature = [0; SIGNATURE_LEN];
+    assert_eq!(
+        block_on(writer.start(max + 1, signature)),
+        Err(Error::TooLarge)
+    );
+    assert_eq!(writer.status(Error::None).phase, Phase::Idle);
+
+    // Nor may chunks run past the length given at start
+    block_on(writer.start(8, signature)).unwrap();
+    assert_eq!(block_on(writer.write(0, &[0; 12])), Err(Error::TooLarge));
+}
+
+#[test]
+fn an_aborted_upload_starts_over() {
+    let flash = MemFlash::new();
+    let mut writer = ImageWriter::new(flash.clone());
+    let mut boot = Boot::default();
+    let image = image();
+    let signature = sign(&key(1), &image);
+
+    block_on(writer.start(image.len() as u32, signature)).unwrap();
+    block_on(writer.write(0, &image[..MAX_CHUNK_LEN])).unwrap();
+    writer.abort();
+    assert_eq!(writer.status(Error::None).phase, Phase::Idle);
+    let chunk = writer.write(MAX_CHUNK_LEN as u32, &image[MAX_CHUNK_LEN..]);
+    assert_eq!(block_on(chunk), Err(Error::NotStarted));
+    let finished = block_on(writer.finish(&public(1), &mut boot));
+    assert_eq!(finished, Err(Error::NotStarted));
+
+    // Starting again erases what the aborted upload wrote
+    upload(&mut writer, &image, signature);
+    block_on(writer.finish(&public(1), &mut boot)).unwrap();
+    assert!(boot.marked);
+    assert_eq!(flash.contents(image.len()), image);
+}
+
+#[test]
+fn an_incomplete_image_is_not_verified() {
+    let mut writer = ImageWriter::new(MemFlash::new());
+    let mut boot = Boot::default();
+    let image = image();
+    block_on(writer.start(image.len() as u32, sign(&key(1), &image))).unwrap();
+    block_on(writer.write(0, &image[..MAX_CHUNK_LEN])).unwrap();
+
+    let finished = block_on(writer.finish(&public(1), &mut boot));
+    assert_eq!(finished, Err(Error::Incomplete));
+    assert!(!boot.marked);
+}
+
+#[test]
+fn a_bad_signature_is_not_handed_over() {
+    let image = image();
+    let mut tampered = image.clone();
+    tampered[500] ^= 1;
+    let cases = [
+        ("tampered image", tampered.as_slice(), sign(&key(1), &image)),
+        ("other key", image.as_slice(), sign(&key(2), &image)),
+        ("garbage", image.as_slice(), [0xa5; SIGNATURE_LEN]),
+    ];
+
+    for (case, uploaded, signature) in cases {
+        let mut writer = ImageWriter::new(MemFlash::new());
+        let mut boot = Boot::default();
+        upload(&mut writer, uploaded, signature);
+
+        let finished = block_on(writer.finish(&public(1), &mut boot));
+        assert_eq!(finished, Err(Error::BadSignature), "{case}");
+        assert!(!boot.marked, "{case}");
+        assert_eq!(writer.status(Error::None).phase, Phase::Idle, "{case}");
+    }
+}
+
+#[test]
+fn the_bootloader_may_refuse_the_image() {
+    let mut writer = ImageWriter::new(MemFlash::new());
+    let image = image();
+    upload(&mut writer, &image, sign(&key(1), &image));
+
+    let finished = block_on(writer.finish(&public(1), &mut Refusing));
+    assert_eq!(finished, Err(Error::BadSignature));
+    assert_eq!(writer.status(Error::None).phase, Phase::Idle);
+}
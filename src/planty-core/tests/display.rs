@@ -0,0 +1,247 @@
+use planty_core::display::{
+    moisture_level, render, Faults, Frame, Status, DRY_READING, FAULT_TICKS, LOW_BATTERY,
+    WET_READING,
+};
+
+fn ascii(frame: Frame) -> String {
+    frame
+        .iter()
+        .map(|row| {
+            (0..5)
+                .map(|x| if row & (0b10000 >> x) != 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn snapshot(status: &Status, tick: u32) -> String {
+    ascii(render(status, tick))
+}
+
+#[test]
+fn no_reading_yet() {
+    assert_eq!(
+        snapshot(&Status::default(), 0),
+        "\
+.....
+.....
+.###.
+.....
+....."
+    );
+}
+
+#[test]
+fn bar_graph_follows_moisture() {
+    let dry = Status {
+        moisture: Some(DRY_READING),
+        ..Default::default()
+    };
+    assert_eq!(
+        snapshot(&dry, 0),
+        "\
+.....
+.....
+.....
+.....
+....."
+    );
+
+    let half = Status {
+        moisture: Some((DRY_READING + WET_READING) / 2),
+        ..Default::default()
+    };
+    assert_eq!(
+        snapshot(&half, 0),
+        "\
+.....
+.....
+####.
+####.
+####."
+    );
+
+    let wet = Status {
+        moisture: Some(WET_READING),
+        ..Default::default()
+    };
+    assert_eq!(
+        snapshot(&wet, 0),
+        "\
+####.
+####.
+####.
+####.
+####."
+    );
+}
+
+#[test]
+fn moisture_level_is_clamped() {
+    assert_eq!(moisture_level(0), 5);
+    assert_eq!(moisture_level(u16::MAX), 0);
+}
+
+#[test]
+fn ble_icon_when_connected() {
+    let status = Status {
+        moisture: Some(DRY_READING),
+        connected: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        snapshot(&status, 0),
+        "\
+....#
+.....
+.....
+.....
+....."
+    );
+}
+
+#[test]
+fn watering_animation_cycles() {
+    let status = Status {
+        moisture: Some(DRY_READING),
+        watering: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        snapshot(&status, 0),
+        "\
+.#...
+.....
+..#..
+.....
+####."
+    );
+    assert_eq!(
+        snapshot(&status, 1),
+        "\
+.....
+.#...
+.....
+..#..
+####."
+    );
+    assert_eq!(render(&status, 0), render(&status, 4));
+}
+
+#[test]
+fn fault_glyphs() {
+    let status = |faults| Status {
+        moisture: Some(WET_READING),
+        faults,
+        ..Default::default()
+    };
+
+    let pump = status(Faults {
+        pump_timeout: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        snapshot(&pump, 0),
+        "\
+#####
+.#.#.
+..#..
+.#.#.
+#####"
+    );
+
+    let battery = status(Faults {
+        low_battery: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        snapshot(&battery, 0),
+        "\
+..#..
+.#.#.
+.#.#.
+.#.#.
+.###."
+    );
+
+    let probe = status(Faults {
+        probe: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        snapshot(&probe, 0),
+        "\
+.###.
+#...#
+..##.
+.....
+..#.."
+    );
+}
+
+#[test]
+fn faults_take_turns_with_the_normal_view() {
+    let status = Status {
+        moisture: Some(WET_READING),
+        faults: Faults {
+            low_battery: true,
+            probe: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let normal = Status {
+        faults: Faults::default(),
+        ..status
+    };
+
+    let battery = render(&status, 0);
+    let probe = render(&status, FAULT_TICKS);
+    assert_ne!(battery, probe);
+    assert_eq!(render(&status, FAULT_TICKS - 1), battery);
+    assert_eq!(render(&status, 2 * FAULT_TICKS), render(&normal, 0));
+    assert_eq!(render(&status, 3 * FAULT_TICKS), battery);
+}
+
+#[test]
+fn attention() {
+    assert!(!Status::default().needs_attention());
+    assert!(Status {
+        watering: true,
+        ..Default::default()
+    }
+    .needs_attention());
+    for faults in [
+        Faults {
+            pump_timeout: true,
+            ..Default::default()
+        },
+        Faults {
+            probe: true,
+            ..Default::default()
+        },
+    ] {
+        assert!(Status {
+            faults,
+            ..Default::default()
+        }
+        .needs_attention());
+    }
+}
+
+#[test]
+fn a_low_battery_alone_lets_the_display_sleep() {
+    let status = Status {
+        moisture: Some(WET_READING),
+        faults: Faults {
+            low_battery: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(!status.needs_attention());
+    // It still shows while the display is on
+    assert_eq!(render(&status, 0), LOW_BATTERY);
+}
@@ -0,0 +1,199 @@
+use planty_core::gestures::{Button, Config, Gesture, Recognizer};
+
+/// Plays a timeline of `(ms, button, pressed)` edges the way the firmware
+/// does, polling at every deadline, and returns the gestures with the time
+/// they were recognized.
+fn run(config: Config, edges: &[(u64, Button, bool)], until: u64) -> Vec<(u64, Gesture)> {
+    let mut recognizer = Recognizer::new(config);
+    let mut gestures = Vec::new();
+
+    for &(at, button, pressed) in edges {
+        drain(&mut recognizer, &mut gestures, at);
+        recognizer.edge(button, pressed, at, |gesture| gestures.push((at, gesture)));
+    }
+    drain(&mut recognizer, &mut gestures, until);
+    gestures
+}
+
+fn drain(recognizer: &mut Recognizer, gestures: &mut Vec<(u64, Gesture)>, now: u64) {
+    while let Some(deadline) = recognizer.deadline().filter(|&deadline| deadline <= now) {
+        recognizer.poll(deadline, |gesture| gestures.push((deadline, gesture)));
+    }
+}
+
+fn default(edges: &[(u64, Button, bool)]) -> Vec<(u64, Gesture)> {
+    run(Config::default(), edges, 10_000)
+}
+
+use Button::{A, B};
+
+#[test]
+fn short_press_waits_out_the_double_click_window() {
+    assert_eq!(
+        default(&[(0, A, true), (100, A, false)]),
+        [(400, Gesture::Short(A))]
+    );
+}
+
+#[test]
+fn long_press_is_reported_while_held() {
+    assert_eq!(
+        default(&[(0, B, true), (2000, B, false)]),
+        [(800, Gesture::Long(B)), (2000, Gesture::LongEnd(B))]
+    );
+}
+
+#[test]
+fn double_click() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (100, A, false),
+            (250, A, true),
+            (350, A, false)
+        ]),
+        [(250, Gesture::Double(A))]
+    );
+}
+
+#[test]
+fn holding_the_second_click_is_not_a_long_press() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (100, A, false),
+            (250, A, true),
+            (3000, A, false)
+        ]),
+        [(250, Gesture::Double(A))]
+    );
+}
+
+#[test]
+fn slow_clicks_are_two_short_presses() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (100, A, false),
+            (600, A, true),
+            (700, A, false)
+        ]),
+        [(400, Gesture::Short(A)), (1000, Gesture::Short(A))]
+    );
+}
+
+#[test]
+fn chord() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (50, B, true),
+            (3000, A, false),
+            (3100, B, false)
+        ]),
+        [(50, Gesture::Chord)]
+    );
+}
+
+#[test]
+fn no_chord_once_a_long_press_started() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (1000, B, true),
+            (1100, B, false),
+            (2000, A, false)
+        ]),
+        [
+            (800, Gesture::Long(A)),
+            (1400, Gesture::Short(B)),
+            (2000, Gesture::LongEnd(A)),
+        ]
+    );
+}
+
+#[test]
+fn buttons_are_independent() {
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (100, A, false),
+            (200, B, true),
+            (1500, B, false)
+        ]),
+        [
+            (400, Gesture::Short(A)),
+            (1000, Gesture::Long(B)),
+            (1500, Gesture::LongEnd(B)),
+        ]
+    );
+}
+
+#[test]
+fn repeated_levels_do_not_desynchronize() {
+    // The release between the two presses went missing
+    assert_eq!(
+        default(&[
+            (0, A, true),
+            (100, A, true),
+            (200, A, false),
+            (300, A, false)
+        ]),
+        [(500, Gesture::Short(A))]
+    );
+    // A release without a press is ignored
+    assert_eq!(
+        default(&[(0, A, false), (100, A, true), (200, A, false)]),
+        [(500, Gesture::Short(A))]
+    );
+}
+
+#[test]
+fn configurable_hold() {
+    let config = Config {
+        long_press_ms: 2000,
+        ..Config::default()
+    };
+    assert_eq!(
+        run(config, &[(0, A, true), (1500, A, false)], 10_000),
+        [(1800, Gesture::Short(A))]
+    );
+    assert_eq!(
+        run(config, &[(0, A, true), (2500, A, false)], 10_000),
+        [(2000, Gesture::Long(A)), (2500, Gesture::LongEnd(A))]
+    );
+}
+
+#[test]
+fn double_clicks_off() {
+    let config = Config {
+        double_click_ms: 0,
+        ..Config::default()
+    };
+    assert_eq!(
+        run(
+            config,
+            &[
+                (0, A, true),
+                (100, A, false),
+                (150, A, true),
+                (250, A, false)
+            ],
+            10_000
+        ),
+        [(100, Gesture::Short(A)), (250, Gesture::Short(A))]
+    );
+}
+
+#[test]
+fn late_edges_settle_earlier_deadlines_first() {
+    // The caller missed the deadline of the first click entirely
+    let mut recognizer = Recognizer::new(Config::default());
+    let mut gestures = Vec::new();
+    recognizer.edge(A, true, 0, |gesture| gestures.push(gesture));
+    recognizer.edge(A, false, 100, |gesture| gestures.push(gesture));
+    recognizer.edge(A, true, 5000, |gesture| gestures.push(gesture));
+    recognizer.edge(A, false, 5100, |gesture| gestures.push(gesture));
+    recognizer.poll(6000, |gesture| gestures.push(gesture));
+    assert_eq!(gestures, [Gesture::Short(A), Gesture::Short(A)]);
+}
@@ -0,0 +1,76 @@
+use planty_core::{
+    bus::Push,
+    outbox::{Coalesce, Outbox},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Update {
+    Moisture(u16),
+    Voltage(u16),
+    Ack(u8),
+}
+
+impl Coalesce for Update {
+    fn kind(&self) -> Option<usize> {
+        match self {
+            Update::Moisture(_) => Some(0),
+            Update::Voltage(_) => Some(1),
+            Update::Ack(_) => None,
+        }
+    }
+}
+
+fn drain(outbox: &mut Outbox<Update, 2, 3>) -> Vec<Update> {
+    std::iter::from_fn(|| outbox.pop()).collect()
+}
+
+#[test]
+fn only_the_latest_of_a_kind_is_kept() {
+    let mut outbox = Outbox::<Update, 2, 3>::new();
+    assert_eq!(outbox.push(Update::Moisture(1)), Push::Queued);
+    assert_eq!(outbox.push(Update::Voltage(3000)), Push::Queued);
+    for reading in 2..100 {
+        assert_eq!(outbox.push(Update::Moisture(reading)), Push::Coalesced);
+    }
+
+    assert_eq!(
+        drain(&mut outbox),
+        [Update::Moisture(99), Update::Voltage(3000)]
+    );
+    assert!(outbox.is_empty());
+}
+
+#[test]
+fn acks_are_kept_one_by_one_and_go_first() {
+    let mut outbox = Outbox::<Update, 2, 3>::new();
+    outbox.push(Update::Moisture(1));
+    outbox.push(Update::Ack(1));
+    outbox.push(Update::Moisture(2));
+    outbox.push(Update::Ack(2));
+
+    assert_eq!(
+        drain(&mut outbox),
+        [Update::Ack(1), Update::Ack(2), Update::Moisture(2)]
+    );
+}
+
+#[test]
+fn acks_beyond_the_capacity_are_dropped() {
+    let mut outbox = Outbox::<Update, 2, 3>::new();
+    for sequence in 0..3 {
+        assert_eq!(outbox.push(Update::Ack(sequence)), Push::Queued);
+    }
+    assert_eq!(outbox.push(Update::Ack(3)), Push::Dropped);
+    // Coalescing updates still fit
+    assert_eq!(outbox.push(Update::Moisture(1)), Push::Queued);
+
+    assert_eq!(
+        drain(&mut outbox),
+        [
+            Update::Ack(0),
+            Update::Ack(1),
+            Update::Ack(2),
+            Update::Moisture(1)
+        ]
+    );
+}
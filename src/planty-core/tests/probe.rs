@@ -0,0 +1,79 @@
+use std::fs;
+
+use planty_core::probe::{Config, Health, Monitor};
+
+/// Runs every trace in `tests/traces` through a fresh monitor. Each line is
+/// a reading, the supply voltage it was taken at and the expected health.
+#[test]
+fn traces_are_classified_as_expected() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/traces");
+    let mut traces = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let mut monitor = Monitor::new(Config::default());
+        let trace = fs::read_to_string(&path).unwrap();
+        let lines = trace
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.starts_with('#'));
+        for (number, line) in lines {
+            let [reading, vdd, expected] = line.split(' ').collect::<Vec<_>>()[..] else {
+                panic!("{}:{}: malformed line", path.display(), number + 1);
+            };
+            let health = monitor.check(reading.parse().unwrap(), vdd.parse().unwrap());
+            assert_eq!(
+                health,
+                parse(expected),
+                "{}:{}: {line}",
+                path.display(),
+                number + 1
+            );
+        }
+        traces += 1;
+    }
+    assert!(traces >= 6);
+}
+
+fn parse(name: &str) -> Health {
+    match name {
+        "ok" => Health::Ok,
+        "open" => Health::Open,
+        "short" => Health::Short,
+        "stuck" => Health::Stuck,
+        "jump" => Health::Jump,
+        "out_of_range" => Health::OutOfRange,
+        _ => panic!("unknown health {name}"),
+    }
+}
+
+#[test]
+fn falling_readings_are_never_a_jump() {
+    let mut monitor = Monitor::new(Config::default());
+    assert_eq!(monitor.check(2800, 3000), Health::Ok);
+    assert_eq!(monitor.check(1200, 3000), Health::Ok);
+}
+
+#[test]
+fn calibrating_accepts_the_new_level() {
+    let mut monitor = Monitor::new(Config::default());
+    monitor.check(1500, 3000);
+    assert_eq!(monitor.check(2500, 3000), Health::Jump);
+    monitor.reset();
+    assert_eq!(monitor.check(2500, 3000), Health::Ok);
+}
+
+#[test]
+fn the_short_threshold_follows_the_supply() {
+    let mut monitor = Monitor::new(Config::default());
+    // Dry air on a fresh battery, and a short on a weak one
+    assert_eq!(monitor.check(2840, 3000), Health::Ok);
+    assert_eq!(monitor.check(2840, 2500), Health::Short);
+}
+
+#[test]
+fn health_round_trips() {
+    for value in 0..=5 {
+        assert_eq!(Health::decode(value).unwrap() as u8, value);
+    }
+    assert_eq!(Health::decode(6), None);
+}
@@ -0,0 +1,35 @@
+use planty_core::pump::{Ack, Command, Outcome};
+
+#[test]
+fn ack_round_trips() {
+    for outcome in [
+        Outcome::Queued,
+        Outcome::Done,
+        Outcome::QueueFull,
+        Outcome::LowSupply,
+        Outcome::Unchanged,
+        Outcome::Fault,
+    ] {
+        let ack = Ack {
+            sequence: 7,
+            command: Command::Start,
+            outcome,
+        };
+        assert_eq!(Ack::decode(&ack.encode()), Some(ack));
+    }
+    assert_eq!(
+        Ack {
+            sequence: 200,
+            command: Command::Stop,
+            outcome: Outcome::LowSupply
+        }
+        .encode(),
+        [200, 0, 3]
+    );
+}
+
+#[test]
+fn malformed_acks_are_rejected() {
+    assert_eq!(Ack::decode(&[1, 1]), None);
+    assert_eq!(Ack::decode(&[1, 1, 9]), None);
+}
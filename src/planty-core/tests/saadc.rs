@@ -0,0 +1,49 @@
+use planty_core::saadc::{
+    ratiometric, to_counts, to_millivolts, OffsetCalibration, RECALIBRATE_MS, REFERENCE_MILLIVOLTS,
+};
+
+#[test]
+fn conversions_round_trip() {
+    assert_eq!(to_millivolts(to_counts(3000) as i16), 2999);
+    assert_eq!(to_counts(3600), 4096);
+    assert_eq!(to_millivolts(-3), 0);
+}
+
+#[test]
+fn ratiometric_readings_ignore_the_battery() {
+    // A probe powered from the supply reads in proportion to it, so 2000
+    // at the reference is 1600 at 2.4 V
+    assert_eq!(ratiometric(1600, 2400), 2000);
+    assert_eq!(ratiometric(2000, REFERENCE_MILLIVOLTS), 2000);
+    assert_eq!(ratiometric(4000, 1800), 4095);
+    assert_eq!(ratiometric(1234, 0), 1234);
+}
+
+#[test]
+fn offset_is_calibrated_first_and_after_drifting() {
+    let mut calibration = OffsetCalibration::new();
+    let due = |calibration: &mut OffsetCalibration, t| calibration.due(0, Some(t));
+    // 20 °C, in quarter degrees
+    assert!(due(&mut calibration, 80));
+    assert!(!due(&mut calibration, 80));
+    assert!(!due(&mut calibration, 80 + 19));
+    assert!(due(&mut calibration, 80 + 20));
+    // Measured from the last calibration, in either direction
+    assert!(!due(&mut calibration, 100 - 19));
+    assert!(due(&mut calibration, 100 - 20));
+}
+
+#[test]
+fn offset_is_calibrated_on_a_timer_without_a_temperature() {
+    let mut calibration = OffsetCalibration::new();
+    assert!(calibration.due(0, None));
+    assert!(!calibration.due(RECALIBRATE_MS - 1, None));
+    assert!(calibration.due(RECALIBRATE_MS, None));
+    assert!(!calibration.due(RECALIBRATE_MS + 1, None));
+
+    // Once the temperature reads again it becomes the reference, and the
+    // timer only matters while it can't be read
+    assert!(calibration.due(RECALIBRATE_MS + 2, Some(80)));
+    assert!(!calibration.due(3 * RECALIBRATE_MS, Some(80)));
+    assert!(calibration.due(3 * RECALIBRATE_MS, None));
+}
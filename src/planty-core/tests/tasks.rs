@@ -0,0 +1,464 @@
+//! The firmware's task loops, wired together the way `08-ble-watering` spawns
+//! them, on a simulated plant. Time only moves when a test advances the mock
+//! driver, so every run sees the same schedule.
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Mutex, MutexGuard},
+};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant, MockDriver};
+use futures::{executor::LocalPool, task::LocalSpawnExt};
+use planty_core::{
+    bus::{Priority, Push},
+    control::{Event, Rejection, State, Transition, MAX_WATERING_MS},
+    display::DRY_READING,
+    gestures::Button,
+    outbox::{Coalesce, Outbox},
+    probe::Health,
+    pump::{Ack, Command, Outcome, Request},
+    tasks::{
+        self, gesture_event, Edges, Events, Measurement, Message, Plant, Sample, Source,
+        DEFAULT_THRESHOLD, WATERING_MS,
+    },
+    temperature::Compensation,
+};
+
+type Raw = CriticalSectionRawMutex;
+
+const INTERVAL: Duration = Duration::from_secs(10);
+const DRY: u16 = DEFAULT_THRESHOLD + 500;
+const WET: u16 = DEFAULT_THRESHOLD - 500;
+const FRESH_BATTERY: u16 = 3000;
+
+/// The mock driver's clock is shared by every test in this file.
+static CLOCK: Mutex<()> = Mutex::new(());
+
+/// What the firmware publishes for the BLE layer, in short.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Update {
+    Moisture(u16),
+    PumpStatus(Ack),
+}
+
+impl Coalesce for Update {
+    fn kind(&self) -> Option<usize> {
+        match self {
+            Update::Moisture(_) => Some(0),
+            Update::PumpStatus(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct World {
+    moisture: u16,
+    vdd_millivolts: u16,
+    quarter_celsius: Option<i32>,
+    compensation: Compensation,
+    /// When the pump was switched, in milliseconds since the test started.
+    switched: Vec<(u64, bool)>,
+    measurements: Vec<Measurement>,
+    thresholds: Vec<u16>,
+    /// When manual watering was cut off at the maximum.
+    timeouts: Vec<u64>,
+    replies: Vec<(Message, Result<Transition, Rejection>)>,
+    /// Published the way `08-ble-watering` does. Nothing drains it unless a
+    /// test does, as if no central were connected.
+    outbox: Outbox<Update, 1, 8>,
+}
+
+struct Sim {
+    world: Rc<RefCell<World>>,
+    start: Instant,
+}
+
+impl Plant for Sim {
+    async fn sample(&mut self) -> Sample {
+        let world = self.world.borrow();
+        Sample {
+            moisture: world.moisture,
+            vdd_millivolts: world.vdd_millivolts,
+            quarter_celsius: world.quarter_celsius,
+        }
+    }
+
+    fn set_pump(&mut self, on: bool) {
+        let at = Instant::now().duration_since(self.start).as_millis();
+        self.world.borrow_mut().switched.push((at, on));
+    }
+
+    fn measured(&mut self, measurement: &Measurement) {
+        let mut world = self.world.borrow_mut();
+        world.measurements.push(*measurement);
+        world
+            .outbox
+            .push(Update::Moisture(measurement.sample.moisture));
+    }
+
+    fn calibrated(&mut self, _reading: u16, threshold: u16) {
+        self.world.borrow_mut().thresholds.push(threshold);
+    }
+
+    fn pump_timed_out(&mut self) {
+        let at = Instant::now().duration_since(self.start).as_millis();
+        self.world.borrow_mut().timeouts.push(at);
+    }
+
+    fn compensation(&mut self) -> Compensation {
+        self.world.borrow().compensation
+    }
+
+    fn reply(&mut self, message: Message, _state: State, result: Result<Transition, Rejection>) {
+        let mut world = self.world.borrow_mut();
+        if let (_, Source::Ble(request)) = message {
+            let ack = request.ack(result.into());
+            assert_eq!(world.outbox.push(Update::PumpStatus(ack)), Push::Queued);
+        }
+        world.replies.push((message, result));
+    }
+}
+
+struct Harness {
+    pool: LocalPool,
+    world: Rc<RefCell<World>>,
+    events: &'static Events<Raw, 4>,
+    edges: &'static Edges<Raw, 4>,
+    _clock: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    /// Spawns the measurement, gesture and control loops on a plant with
+    /// `moisture` and `vdd_millivolts`.
+    fn new(moisture: u16, vdd_millivolts: u16) -> Self {
+        let clock = CLOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let world = Rc::new(RefCell::new(World {
+            moisture,
+            vdd_millivolts,
+            ..World::default()
+        }));
+        let events: &'static Events<Raw, 4> = Box::leak(Box::new(Events::new()));
+        let edges: &'static Edges<Raw, 4> = Box::leak(Box::new(Edges::new()));
+
+        let pool = LocalPool::new();
+        let spawner = pool.spawner();
+        let plant = Sim {
+            world: world.clone(),
+            start: Instant::now(),
+        };
+        spawner
+            .spawn_local(async move {
+                tasks::measure(events, || INTERVAL, || {}).await;
+            })
+            .unwrap();
+        spawner
+            .spawn_local(async move {
+                tasks::gestures(
+                    edges,
+                    || {},
+                    |gesture| {
+                        if let Some((priority, event)) = gesture_event(gesture) {
+                            events.send(priority, Source::Button, event);
+                        }
+                    },
+                )
+                .await;
+            })
+            .unwrap();
+        spawner
+            .spawn_local(async move {
+                tasks::control(events, plant).await;
+            })
+            .unwrap();
+
+        Self {
+            pool,
+            world,
+            events,
+            edges,
+            _clock: clock,
+        }
+    }
+
+    /// Lets time pass a millisecond at a time, running everything that is
+    /// due in between.
+    fn advance(&mut self, duration: Duration) {
+        let end = Instant::now() + duration;
+        self.pool.run_until_stalled();
+        while Instant::now() < end {
+            MockDriver::get().advance(Duration::from_millis(1));
+            self.pool.run_until_stalled();
+        }
+    }
+
+    fn hold(&mut self, button: Button, duration: Duration) {
+        self.edges.try_send((button, true, Instant::now())).unwrap();
+        self.advance(duration);
+        self.edges
+            .try_send((button, false, Instant::now()))
+            .unwrap();
+        self.pool.run_until_stalled();
+    }
+
+    /// Sends a pump command as if written over BLE.
+    fn command(&self, sequence: u8, command: Command) -> Request {
+        let request = Request { sequence, command };
+        let priority = match command {
+            Command::Start => Priority::User,
+            Command::Stop => Priority::Safety,
+        };
+        self.events
+            .send(priority, Source::Ble(request), command.into());
+        request
+    }
+
+    fn switched(&self) -> Vec<(u64, bool)> {
+        self.world.borrow().switched.clone()
+    }
+
+    /// Whether the pump was ever switched on. Entering `Fault` switches it
+    /// off whether it was running or not.
+    fn pumped(&self) -> bool {
+        self.switched().iter().any(|&(_, on)| on)
+    }
+}
+
+#[test]
+fn dry_soil_gets_one_measurement_and_one_pump_pulse() {
+    let mut harness = Harness::new(DRY, FRESH_BATTERY);
+    harness.advance(Duration::from_secs(10));
+
+    let world = harness.world.borrow();
+    assert_eq!(world.measurements.len(), 1);
+    assert!(world.measurements[0].dry);
+    assert_eq!(world.measurements[0].watering_ms, Some(WATERING_MS));
+    assert_eq!(world.switched, [(10_000, true)]);
+    drop(world);
+
+    harness.advance(Duration::from_secs(6));
+    assert_eq!(harness.switched(), [(10_000, true), (15_000, false)]);
+    // Automatic watering is meant to stop by itself
+    assert!(harness.world.borrow().timeouts.is_empty());
+}
+
+#[test]
+fn wet_soil_is_measured_but_not_watered() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    harness.advance(Duration::from_secs(35));
+
+    let world = harness.world.borrow();
+    assert_eq!(world.measurements.len(), 3);
+    assert!(world.measurements.iter().all(|m| !m.dry));
+    assert!(world.switched.is_empty());
+}
+
+#[test]
+fn holding_a_waters_until_released() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    harness.hold(Button::A, Duration::from_secs(3));
+
+    assert_eq!(harness.switched(), [(800, true), (3_000, false)]);
+}
+
+#[test]
+fn holding_a_stops_at_the_maximum() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    harness.hold(Button::A, Duration::from_millis(MAX_WATERING_MS + 5_000));
+
+    assert_eq!(
+        harness.switched(),
+        [(800, true), (800 + MAX_WATERING_MS, false)]
+    );
+    let world = harness.world.borrow();
+    assert_eq!(world.timeouts, [800 + MAX_WATERING_MS]);
+    // Letting go afterwards has nothing left to stop
+    let (message, result) = world.replies.last().unwrap();
+    assert_eq!(*message, (Event::WateringComplete, Source::Button));
+    assert_eq!(*result, Err(Rejection::NotWatering));
+}
+
+#[test]
+fn a_low_supply_keeps_the_pump_off() {
+    let mut harness = Harness::new(DRY, 2200);
+    harness.hold(Button::A, Duration::from_secs(2));
+    harness.advance(Duration::from_secs(10));
+
+    // The reading is at the rail of the weak supply, so the probe also
+    // counts as shorted
+    assert!(!harness.pumped());
+    let world = harness.world.borrow();
+    assert!(world
+        .replies
+        .contains(&((Event::Water, Source::Button), Err(Rejection::LowSupply))));
+    assert_eq!(world.measurements.len(), 1);
+    assert!(world.measurements[0].low_battery);
+    assert!(world.measurements[0].battery_went_low);
+    assert_eq!(world.measurements[0].watering_ms, None);
+}
+
+#[test]
+fn a_weak_supply_shortens_automatic_watering() {
+    let mut harness = Harness::new(DRY, 2400);
+    harness.advance(Duration::from_secs(13));
+
+    assert_eq!(harness.switched(), [(10_000, true), (12_000, false)]);
+}
+
+#[test]
+fn a_ble_start_switches_the_pump_and_acknowledges_the_write() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    let start = harness.command(42, Command::Start);
+    harness.advance(Duration::from_millis(1));
+    let again = harness.command(43, Command::Start);
+    harness.advance(Duration::from_millis(1));
+
+    assert_eq!(harness.switched(), [(0, true)]);
+    let mut world = harness.world.borrow_mut();
+    let published: Vec<_> = std::iter::from_fn(|| world.outbox.pop()).collect();
+    let done = Ack {
+        sequence: 42,
+        command: Command::Start,
+        outcome: Outcome::Done,
+    };
+    assert_eq!(start.ack(Outcome::Done), done);
+    assert_eq!(
+        published,
+        [
+            Update::PumpStatus(done),
+            Update::PumpStatus(again.ack(Outcome::Unchanged))
+        ]
+    );
+}
+
+#[test]
+fn a_ble_stop_cuts_automatic_watering_short() {
+    let mut harness = Harness::new(DRY, FRESH_BATTERY);
+    harness.advance(Duration::from_secs(11));
+
+    let request = Request {
+        sequence: 7,
+        command: Command::Stop,
+    };
+    let source = Source::Ble(request);
+    harness
+        .events
+        .send(Priority::Safety, source, request.command.into());
+    harness.advance(Duration::from_millis(1));
+
+    assert_eq!(harness.switched(), [(10_000, true), (11_000, false)]);
+    let world = harness.world.borrow();
+    let (replied, result) = world.replies.last().unwrap();
+    assert_eq!(*replied, (Event::WateringComplete, source));
+    assert!(result.is_ok());
+}
+
+#[test]
+fn holding_b_calibrates_the_threshold() {
+    let mut harness = Harness::new(DRY, FRESH_BATTERY);
+    harness.hold(Button::B, Duration::from_secs(1));
+    harness.advance(Duration::from_secs(10));
+
+    let world = harness.world.borrow();
+    assert_eq!(world.thresholds, [DRY - 100]);
+    // The current reading is now just above the threshold
+    assert_eq!(world.measurements[0].threshold, DRY - 100);
+    assert!(world.measurements[0].dry);
+}
+
+#[test]
+fn a_probe_in_dry_air_is_not_watered() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    harness.advance(Duration::from_secs(10));
+    // Pulled out of the pot
+    harness.world.borrow_mut().moisture = DRY_READING;
+    harness.advance(Duration::from_secs(10));
+
+    let world = harness.world.borrow();
+    assert_eq!(world.measurements.len(), 2);
+    assert_eq!(world.measurements[1].health, Health::Jump);
+    assert!(world.measurements[1].health_changed);
+    assert!(!world.measurements[1].dry);
+    assert!(world.switched.is_empty());
+}
+
+#[test]
+fn a_disconnected_probe_faults_until_reconnected() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    harness.advance(Duration::from_secs(10));
+    harness.world.borrow_mut().moisture = 0;
+    harness.advance(Duration::from_secs(10));
+
+    let fault = ((Event::Fault, Source::Timer), State::Fault);
+    assert_eq!(
+        harness.world.borrow().replies.last().map(reached),
+        Some(fault)
+    );
+    harness.hold(Button::A, Duration::from_secs(1));
+    assert!(!harness.pumped());
+
+    harness.world.borrow_mut().moisture = WET;
+    harness.advance(Duration::from_secs(10));
+    let world = harness.world.borrow();
+    let cleared = ((Event::FaultCleared, Source::Timer), State::Idle);
+    assert_eq!(world.replies.last().map(reached), Some(cleared));
+    assert_eq!(world.measurements.last().unwrap().health, Health::Ok);
+}
+
+#[test]
+fn cold_soil_is_not_mistaken_for_dry() {
+    let mut harness = Harness::new(DEFAULT_THRESHOLD + 50, FRESH_BATTERY);
+    {
+        let mut world = harness.world.borrow_mut();
+        // 10 °C, where the probe reads 100 higher than at 20 °C
+        world.quarter_celsius = Some(40);
+        world.compensation = Compensation {
+            centicounts_per_celsius: -1000,
+        };
+    }
+    harness.advance(Duration::from_secs(10));
+
+    let world = harness.world.borrow();
+    assert_eq!(
+        world.measurements[0].sample.moisture,
+        DEFAULT_THRESHOLD - 50
+    );
+    assert!(!world.measurements[0].dry);
+    assert!(world.switched.is_empty());
+}
+
+#[test]
+fn updates_wait_while_no_central_is_connected() {
+    let mut harness = Harness::new(WET, FRESH_BATTERY);
+    let mut sent = Vec::new();
+    // A second of watering in the middle of every measurement interval
+    for cycle in 0..3 {
+        harness.advance(Duration::from_secs(2));
+        sent.push(harness.command(cycle * 2, Command::Start));
+        harness.advance(Duration::from_secs(1));
+        sent.push(harness.command(cycle * 2 + 1, Command::Stop));
+        harness.advance(Duration::from_secs(7));
+    }
+    harness.world.borrow_mut().moisture = WET + 100;
+    harness.advance(Duration::from_secs(10));
+
+    let mut world = harness.world.borrow_mut();
+    assert_eq!(world.measurements.len(), 4);
+    assert_eq!(world.switched.len(), 6);
+    // Every pump command is acknowledged, but only the last reading is sent
+    let mut expected: Vec<_> = sent
+        .iter()
+        .map(|request| Update::PumpStatus(request.ack(Outcome::Done)))
+        .collect();
+    expected.push(Update::Moisture(WET + 100));
+    let published: Vec<_> = std::iter::from_fn(|| world.outbox.pop()).collect();
+    assert_eq!(published, expected);
+}
+
+/// The state a message took the controller to, panicking if it was rejected.
+fn reached((message, result): &(Message, Result<Transition, Rejection>)) -> (Message, State) {
+    (*message, result.unwrap().to)
+}
@@ -0,0 +1,66 @@
+use planty_core::temperature::{
+    encode_estimate, Compensation, Estimator, MIN_HISTORY, NO_ESTIMATE, REFERENCE_CELSIUS,
+};
+
+/// A probe in soil of constant moisture that reads 2000 at 20 °C and rises
+/// `centicounts_per_celsius` hundredths of a count per degree.
+fn reading(quarter_celsius: i32, centicounts_per_celsius: i32) -> u16 {
+    (2000 + (quarter_celsius - 80) * centicounts_per_celsius / 400) as u16
+}
+
+#[test]
+fn compensation_is_relative_to_the_reference() {
+    let compensation = Compensation {
+        centicounts_per_celsius: -500,
+    };
+    assert_eq!(compensation.apply(2000, REFERENCE_CELSIUS * 4), 2000);
+    // 10 °C colder reads 50 higher, 10 °C warmer 50 lower
+    assert_eq!(compensation.apply(2050, 40), 2000);
+    assert_eq!(compensation.apply(1950, 120), 2000);
+    assert_eq!(Compensation::NONE.apply(2050, 40), 2050);
+    assert_eq!(compensation.apply(4090, 400), 4095);
+}
+
+#[test]
+fn estimator_recovers_the_coefficient() {
+    let mut estimator = Estimator::new();
+    // A night and a morning: 12 °C up to 22 °C and back, in quarter degrees
+    let temperatures = (48..88).chain((48..88).rev()).cycle();
+    for t in temperatures.take(MIN_HISTORY as usize) {
+        estimator.add(t, reading(t, -800));
+    }
+    let estimate = estimator.estimate().unwrap();
+    // Readings are whole counts, so the fit is close rather than exact
+    assert!((estimate.centicounts_per_celsius + 800).abs() <= 10);
+
+    // Compensating with the estimate takes the temperature back out
+    for t in [48, 68, 87] {
+        let compensated = estimate.apply(reading(t, -800), t);
+        assert!((i32::from(compensated) - 2000).abs() <= 1);
+    }
+}
+
+#[test]
+fn estimator_waits_for_enough_history() {
+    let mut estimator = Estimator::new();
+    for t in (60..100).cycle().take(MIN_HISTORY as usize - 1) {
+        estimator.add(t, reading(t, 300));
+    }
+    assert_eq!(estimator.estimate(), None);
+    estimator.add(60, reading(60, 300));
+    assert!(estimator.estimate().is_some());
+
+    estimator.reset();
+    assert_eq!(estimator.estimate(), None);
+}
+
+#[test]
+fn estimator_needs_the_temperature_to_move() {
+    let mut estimator = Estimator::new();
+    // Only 2 °C apart
+    for t in (80..88).cycle().take(2 * MIN_HISTORY as usize) {
+        estimator.add(t, reading(t, 300));
+    }
+    assert_eq!(estimator.estimate(), None);
+    assert_eq!(encode_estimate(estimator.estimate()), NO_ESTIMATE);
+}
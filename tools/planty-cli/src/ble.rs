@@ -0,0 +1,79 @@
+//! Connecting to a planty over BLE.
+
+use std::{error::Error, time::Duration};
+
+use btleplug::{
+    api::{Central, Characteristic, Manager as _, Peripheral as _, ScanFilter},
+    platform::{Manager, Peripheral},
+};
+use uuid::Uuid;
+
+const DEVICE_NAME: &str = "planty";
+const SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+const SCAN_POLL: Duration = Duration::from_millis(500);
+
+pub const PLANT_SERVICE: Uuid = Uuid::from_u128(0x12345678_1234_5678_1234_56789abcdef0);
+pub const CRASH_RECORD: Uuid = Uuid::from_u128(0x12345678_1234_5678_1234_56789abcdf11);
+
+/// Connects to the first planty found by the first Bluetooth adapter.
+pub async fn connect() -> Result<Peripheral, Box<dyn Error>> {
+    let manager = Manager::new().await?;
+    let adapter = manager
+        .adapters()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or("no Bluetooth adapter found")?;
+
+    adapter
+        .start_scan(ScanFilter {
+            services: vec![PLANT_SERVICE],
+        })
+        .await?;
+    let peripheral = find(&adapter).await;
+    adapter.stop_scan().await?;
+    let peripheral = peripheral?;
+
+    peripheral.connect().await?;
+    peripheral.discover_services().await?;
+    Ok(peripheral)
+}
+
+async fn find(
+    adapter: &impl Central<Peripheral = Peripheral>,
+) -> Result<Peripheral, Box<dyn Error>> {
+    let started = tokio::time::Instant::now();
+    while started.elapsed() < SCAN_TIMEOUT {
+        for peripheral in adapter.peripherals().await? {
+            let properties = peripheral.properties().await?;
+            if properties.and_then(|p| p.local_name).as_deref() == Some(DEVICE_NAME) {
+                return Ok(peripheral);
+            }
+        }
+        tokio::time::sleep(SCAN_POLL).await;
+    }
+    Err(format!("no {DEVICE_NAME} found within {} s", SCAN_TIMEOUT.as_secs()).into())
+}
+
+pub fn characteristic(
+    peripheral: &Peripheral,
+    uuid: Uuid,
+) -> Result<Characteristic, Box<dyn Error>> {
+    peripheral
+        .characteristics()
+        .into_iter()
+        .find(|characteristic| characteristic.uuid == uuid)
+        .ok_or_else(|| {
+            format!("characteristic {uuid} not found; is the firmware up to date?").into()
+        })
+}
+
+/// Runs a BLE exchange to completion on a single threaded runtime.
+pub fn block_on<T>(
+    future: impl std::future::Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future)
+}
@@ -0,0 +1,117 @@
+//! Average current and battery life estimate for each power mode.
+//!
+//! The intervals mirror `src/08-ble-watering/src/power.rs`. The currents and
+//! durations are rough nRF52833 datasheet figures rather than measurements;
+//! `docs/power.md` is based on them.
+
+/// Something the firmware does periodically, or all the time when
+/// `period_s` is `None`.
+pub struct Activity {
+    pub name: &'static str,
+    pub current_ma: f64,
+    pub duration_ms: f64,
+    pub period_s: Option<f64>,
+}
+
+impl Activity {
+    pub fn average_ua(&self) -> f64 {
+        match self.period_s {
+            Some(period_s) => self.current_ma * 1000.0 * self.duration_ms / (period_s * 1000.0),
+            None => self.current_ma * 1000.0,
+        }
+    }
+}
+
+pub struct Mode {
+    pub name: &'static str,
+    pub measurement_interval_s: f64,
+    pub advertising_interval_ms: f64,
+}
+
+pub const MODES: [Mode; 3] = [
+    Mode {
+        name: "normal",
+        measurement_interval_s: 10.0,
+        advertising_interval_ms: 100.0,
+    },
+    Mode {
+        name: "saver",
+        measurement_interval_s: 60.0,
+        advertising_interval_ms: 500.0,
+    },
+    Mode {
+        name: "critical",
+        measurement_interval_s: 300.0,
+        advertising_interval_ms: 2000.0,
+    },
+];
+
+pub struct Assumptions {
+    /// Supply current of the moisture probe, which is powered all the time.
+    pub probe_ma: f64,
+    /// Usable capacity of the battery pack.
+    pub capacity_mah: f64,
+    /// How often the pump runs, on average.
+    pub waterings_per_day: f64,
+}
+
+impl Default for Assumptions {
+    fn default() -> Self {
+        Self {
+            probe_ma: 5.0,
+            capacity_mah: 2000.0,
+            waterings_per_day: 1.0,
+        }
+    }
+}
+
+pub fn activities(mode: &Mode, assumptions: &Assumptions) -> [Activity; 5] {
+    [
+        Activity {
+            name: "sleep (RTC, softdevice idle)",
+            current_ma: 0.003,
+            duration_ms: 0.0,
+            period_s: None,
+        },
+        Activity {
+            name: "moisture probe",
+            current_ma: assumptions.probe_ma,
+            duration_ms: 0.0,
+            period_s: None,
+        },
+        Activity {
+            name: "measurement (CPU + SAADC)",
+            current_ma: 3.0,
+            duration_ms: 2.0,
+            period_s: Some(mode.measurement_interval_s),
+        },
+        Activity {
+            name: "advertising event",
+            current_ma: 5.0,
+            duration_ms: 3.0,
+            period_s: Some(mode.advertising_interval_ms / 1000.0),
+        },
+        Activity {
+            name: "pump",
+            current_ma: 150.0,
+            duration_ms: 5000.0,
+            period_s: Some(86_400.0 / assumptions.waterings_per_day),
+        },
+    ]
+}
+
+pub struct Estimate {
+    pub average_ua: f64,
+    pub life_days: f64,
+}
+
+pub fn estimate(mode: &Mode, assumptions: &Assumptions) -> Estimate {
+    let average_ua: f64 = activities(mode, assumptions)
+        .iter()
+        .map(Activity::average_ua)
+        .sum();
+    Estimate {
+        average_ua,
+        life_days: assumptions.capacity_mah * 1000.0 / average_ua / 24.0,
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    env, fs,
+    io::{self, BufRead},
+    process::ExitCode,
+};
+
+use btleplug::api::{Peripheral as _, WriteType};
+use ed25519_dalek::SigningKey;
+use planty_core::{beacon, crash};
+use rand_core::OsRng;
+
+mod ble;
+mod energy;
+mod sign;
+
+const USAGE: &str = "\
+usage: planty-cli <command>
+
+commands:
+  decode-beacon [HEX...]        decode raw advertisement data, one hex string
+                                per argument or per line on stdin
+  keygen <SECRET> <PUBLIC>      create a firmware signing key pair
+  sign <SECRET> <FIRMWARE> <OUT>
+                                sign an ELF or BIN into an update package
+  energy [--probe-ma MA] [--capacity-mah MAH] [--waterings-per-day N]
+                                estimate current draw and battery life
+  crash [--clear]               fetch the last panic from a planty over BLE,
+                                and optionally clear it";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("decode-beacon") => decode_beacons(&args[1..]),
+        Some("keygen") if args.len() == 3 => report(keygen(&args[1], &args[2])),
+        Some("sign") if args.len() == 4 => report(sign_firmware(&args[1], &args[2], &args[3])),
+        Some("energy") => report(estimate_energy(&args[1..])),
+        Some("crash") if args.len() == 1 => report(ble::block_on(fetch_crash(false))),
+        Some("crash") if args.len() == 2 && args[1] == "--clear" => {
+            report(ble::block_on(fetch_crash(true)))
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn report(result: Result<(), Box<dyn std::error::Error>>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn keygen(secret_path: &str, public_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let key = SigningKey::generate(&mut OsRng);
+    fs::write(secret_path, key.to_bytes())?;
+    fs::write(public_path, key.verifying_key().to_bytes())?;
+
+    println!("wrote {secret_path} and {public_path}");
+    println!(
+        "copy {public_path} to src/08-ble-watering/dfu_public.key and keep {secret_path} private"
+    );
+    Ok(())
+}
+
+fn sign_firmware(
+    secret_path: &str,
+    firmware_path: &str,
+    out_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let secret: [u8; 32] = fs::read(secret_path)?
+        .try_into()
+        .map_err(|_| format!("{secret_path} is not a 32 byte ed25519 secret key"))?;
+    let key = SigningKey::from_bytes(&secret);
+
+    let image = sign::image(&fs::read(firmware_path)?)?;
+    let package = sign::sign(&key, &image)?;
+    sign::verify(&key.verifying_key(), &package)?;
+    fs::write(out_path, &package)?;
+
+    println!("signed {} byte image into {out_path}", image.len());
+    Ok(())
+}
+
+fn estimate_energy(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut assumptions = energy::Assumptions::default();
+    for pair in args.chunks(2) {
+        let [flag, value] = pair else {
+            return Err(format!("missing value for {}", pair[0]).into());
+        };
+        let value: f64 = value.parse()?;
+        match flag.as_str() {
+            "--probe-ma" => assumptions.probe_ma = value,
+            "--capacity-mah" => assumptions.capacity_mah = value,
+            "--waterings-per-day" => assumptions.waterings_per_day = value,
+            _ => return Err(format!("unknown option {flag}").into()),
+        }
+    }
+
+    for mode in &energy::MODES {
+        println!(
+            "{} (measure every {} s, advertise every {} ms)",
+            mode.name, mode.measurement_interval_s, mode.advertising_interval_ms
+        );
+        for activity in energy::activities(mode, &assumptions) {
+            println!("  {:<30} {:>10.1} uA", activity.name, activity.average_ua());
+        }
+        let estimate = energy::estimate(mode, &assumptions);
+        println!(
+            "  {:<30} {:>10.1} uA, {:.0} days on {} mAh",
+            "total", estimate.average_ua, estimate.life_days, assumptions.capacity_mah
+        );
+    }
+    Ok(())
+}
+
+async fn fetch_crash(clear: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let peripheral = ble::connect().await?;
+    let characteristic = ble::characteristic(&peripheral, ble::CRASH_RECORD)?;
+
+    // Empty when there is no record
+    let value = peripheral.read(&characteristic).await?;
+    if value.is_empty() {
+        println!("no crash recorded");
+    } else {
+        println!("{}", crash::Record::decode(&value)?);
+    }
+    if clear {
+        peripheral
+            .write(&characteristic, &[0], WriteType::WithResponse)
+            .await?;
+        println!("cleared");
+    }
+
+    peripheral.disconnect().await?;
+    Ok(())
+}
+
+fn decode_beacons(args: &[String]) -> ExitCode {
+    let mut ok = true;
+
+    if args.is_empty() {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) if !line.trim().is_empty() => ok &= decode_beacon(line.trim()),
+                Ok(_) => {}
+                Err(error) => {
+                    eprintln!("failed to read stdin: {error}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    } else {
+        for arg in args {
+            ok &= decode_beacon(arg);
+        }
+    }
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn decode_beacon(hex: &str) -> bool {
+    let Some(data) = parse_hex(hex) else {
+        eprintln!("{hex}: not a hex string");
+        return false;
+    };
+
+    match beacon::Status::decode_advertisement(&data) {
+        Ok(status) => {
+            let battery = status
+                .battery_percent
+                .map_or_else(|| "unknown".to_string(), |percent| format!("{percent}%"));
+            println!(
+                "moisture={} threshold={} watering={} battery={} low_battery={} probe_fault={}",
+                status.moisture,
+                status.threshold,
+                status.is_watering(),
+                battery,
+                status.is_low_battery(),
+                status.is_probe_fault()
+            );
+            true
+        }
+        Err(error) => {
+            eprintln!("{hex}: {error}");
+            false
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex
+        .bytes()
+        .filter(|b| !matches!(b, b' ' | b':' | b'-'))
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,205 @@
+//! Signed firmware update packages.
+//!
+//! Layout (little endian):
+//!
+//! | offset | size | field                                      |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | magic, `PLTY`                              |
+//! | 4      | 4    | image length                               |
+//! | 8      | 64   | ed25519 signature over SHA-512 of the image |
+//! | 72     | ...  | image, as flashed at the start of `FLASH`  |
+//!
+//! The firmware checks the signature against the public key it was built
+//! with (`src/08-ble-watering/dfu_public.key`) before handing the image to
+//! the bootloader.
+
+use std::fmt;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use object::{
+    elf::{FileHeader32, PT_LOAD},
+    read::elf::{FileHeader, ProgramHeader},
+    Endianness,
+};
+use sha2::{Digest, Sha512};
+
+pub const MAGIC: &[u8; 4] = b"PLTY";
+pub const HEADER_LEN: usize = 72;
+
+/// Start of the active partition, where the application is linked.
+const FLASH_ORIGIN: u32 = 0x27000;
+
+#[derive(Debug)]
+pub enum Error {
+    Elf(object::Error),
+    NoLoadableSegments,
+    SegmentOutOfBounds,
+    WrongOrigin(u32),
+    TooLarge(usize),
+    BadPackage,
+    BadSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Elf(error) => write!(f, "failed to parse ELF: {error}"),
+            Error::NoLoadableSegments => write!(f, "ELF has no loadable segments"),
+            Error::SegmentOutOfBounds => write!(f, "ELF segment data is out of bounds"),
+            Error::WrongOrigin(origin) => write!(
+                f,
+                "image starts at {origin:#x}, expected {FLASH_ORIGIN:#x}; \
+                 was it linked with the bootloader memory layout?"
+            ),
+            Error::TooLarge(len) => write!(f, "image of {len} bytes is too large"),
+            Error::BadPackage => write!(f, "not a signed update package"),
+            Error::BadSignature => write!(f, "signature does not match the image"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<object::Error> for Error {
+    fn from(error: object::Error) -> Self {
+        Error::Elf(error)
+    }
+}
+
+/// Returns the flat image for an ELF file, or the input as is for a raw binary.
+pub fn image(input: &[u8]) -> Result<Vec<u8>, Error> {
+    if input.starts_with(b"\x7fELF") {
+        elf_to_bin(input)
+    } else {
+        Ok(input.to_vec())
+    }
+}
+
+/// Lays out the loadable segments at their load (physical) addresses, the
+/// same way they end up in flash, filling gaps with erased bytes.
+fn elf_to_bin(elf: &[u8]) -> Result<Vec<u8>, Error> {
+    let header = FileHeader32::<Endianness>::parse(elf)?;
+    let endian = header.endian()?;
+
+    let segments: Vec<(u32, &[u8])> = header
+        .program_headers(endian, elf)?
+        .iter()
+        .filter(|segment| segment.p_type(endian) == PT_LOAD && segment.p_filesz(endian) > 0)
+        .map(|segment| Ok((segment.p_paddr(endian), segment.data(endian, elf)?)))
+        .collect::<Result<_, ()>>()
+        .map_err(|()| Error::SegmentOutOfBounds)?;
+
+    let start = segments
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .ok_or(Error::NoLoadableSegments)?;
+    if start != FLASH_ORIGIN {
+        return Err(Error::WrongOrigin(start));
+    }
+    let end = segments
+        .iter()
+        .map(|(address, data)| *address as usize + data.len())
+        .max()
+        .ok_or(Error::NoLoadableSegments)?;
+
+    let mut image = vec![0xff; end - start as usize];
+    for (address, data) in segments {
+        let offset = (address - start) as usize;
+        image[offset..offset + data.len()].copy_from_slice(data);
+    }
+    Ok(image)
+}
+
+pub fn sign(key: &SigningKey, image: &[u8]) -> Result<Vec<u8>, Error> {
+    let len = u32::try_from(image.len()).map_err(|_| Error::TooLarge(image.len()))?;
+    let signature = key.sign(&Sha512::digest(image));
+
+    let mut package = Vec::with_capacity(HEADER_LEN + image.len());
+    package.extend_from_slice(MAGIC);
+    package.extend_from_slice(&len.to_le_bytes());
+    package.extend_from_slice(&signature.to_bytes());
+    package.extend_from_slice(image);
+    Ok(package)
+}
+
+/// Checks a package the way the firmware does and returns its image.
+pub fn verify<'a>(key: &VerifyingKey, package: &'a [u8]) -> Result<&'a [u8], Error> {
+    if package.len() < HEADER_LEN || &package[0..4] != MAGIC {
+        return Err(Error::BadPackage);
+    }
+    let len = u32::from_le_bytes(package[4..8].try_into().unwrap()) as usize;
+    let signature = Signature::from_bytes(package[8..HEADER_LEN].try_into().unwrap());
+    let image = &package[HEADER_LEN..];
+    if image.len() != len {
+        return Err(Error::BadPackage);
+    }
+
+    key.verify(&Sha512::digest(image), &signature)
+        .map_err(|_| Error::BadSignature)?;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key for tests only, never one that firmware is built with.
+    fn test_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn test_image() -> Vec<u8> {
+        (0..1000).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn signed_packages_verify() {
+        let key = test_key(1);
+        let image = image(&test_image()).unwrap();
+        let package = sign(&key, &image).unwrap();
+
+        assert_eq!(&package[..4], MAGIC);
+        assert_eq!(package.len(), HEADER_LEN + image.len());
+        assert_eq!(verify(&key.verifying_key(), &package).unwrap(), image);
+    }
+
+    #[test]
+    fn tampered_images_are_rejected() {
+        let key = test_key(1);
+        let mut package = sign(&key, &test_image()).unwrap();
+        package[HEADER_LEN + 500] ^= 1;
+
+        let result = verify(&key.verifying_key(), &package);
+        assert!(matches!(result, Err(Error::BadSignature)));
+    }
+
+    #[test]
+    fn tampered_signatures_are_rejected() {
+        let key = test_key(1);
+        let mut package = sign(&key, &test_image()).unwrap();
+        package[8] ^= 1;
+
+        let result = verify(&key.verifying_key(), &package);
+        assert!(matches!(result, Err(Error::BadSignature)));
+    }
+
+    #[test]
+    fn packages_signed_with_another_key_are_rejected() {
+        let package = sign(&test_key(2), &test_image()).unwrap();
+
+        let result = verify(&test_key(1).verifying_key(), &package);
+        assert!(matches!(result, Err(Error::BadSignature)));
+    }
+
+    #[test]
+    fn truncated_packages_are_rejected() {
+        let key = test_key(1);
+        let package = sign(&key, &test_image()).unwrap();
+
+        for len in [0, HEADER_LEN - 1, package.len() - 1] {
+            let result = verify(&key.verifying_key(), &package[..len]);
+            assert!(matches!(result, Err(Error::BadPackage)), "{len} bytes");
+        }
+    }
+}
@@ -0,0 +1,70 @@
+# nRF52 SAADC, just enough for embassy-nrf's one-shot `Saadc::sample` and
+# `Saadc::calibrate`.
+#
+# Tasks complete at once: a write to TASKS_SAMPLE stores one result per
+# enabled channel at RESULT.PTR and raises EVENTS_END before the CPU runs its
+# next instruction, so the driver finds the event set on its first poll and
+# no interrupt is needed.
+#
+# The inputs live in test registers past the real ones, one per PSELP value:
+# INPUTS + 4 * 3 is AIN2 and INPUTS + 4 * 9 is VDD. Each holds the raw
+# result in counts.
+
+from Antmicro.Renode.Core import EmulationManager
+
+TASKS_START = 0x000
+TASKS_SAMPLE = 0x004
+TASKS_STOP = 0x008
+TASKS_CALIBRATEOFFSET = 0x00C
+EVENTS_STARTED = 0x100
+EVENTS_END = 0x104
+EVENTS_DONE = 0x108
+EVENTS_RESULTDONE = 0x10C
+EVENTS_CALIBRATEDONE = 0x110
+EVENTS_STOPPED = 0x114
+CH_PSELP = 0x510
+CH_STRIDE = 0x10
+CHANNELS = 8
+RESULT_PTR = 0x62C
+RESULT_MAXCNT = 0x630
+RESULT_AMOUNT = 0x634
+INPUTS = 0xF00
+
+
+def system_bus():
+    found, machine = EmulationManager.Instance.CurrentEmulation.TryGetMachineForPeripheral(self)
+    return machine.SystemBus
+
+
+def sample():
+    bus = system_bus()
+    address = registers.get(RESULT_PTR, 0)
+    limit = registers.get(RESULT_MAXCNT, 0)
+    amount = 0
+    for channel in range(CHANNELS):
+        source = registers.get(CH_PSELP + channel * CH_STRIDE, 0)
+        if source == 0 or amount >= limit:
+            continue
+        counts = registers.get(INPUTS + 4 * source, 0)
+        bus.WriteWord(address + 2 * amount, counts & 0xFFFF)
+        amount += 1
+    registers[RESULT_AMOUNT] = amount
+    for event in (EVENTS_RESULTDONE, EVENTS_DONE, EVENTS_END):
+        registers[event] = 1
+
+
+if request.isInit:
+    registers = {}
+elif request.isWrite:
+    registers[request.offset] = request.value
+    if request.value:
+        if request.offset == TASKS_START:
+            registers[EVENTS_STARTED] = 1
+        elif request.offset == TASKS_SAMPLE:
+            sample()
+        elif request.offset == TASKS_STOP:
+            registers[EVENTS_STOPPED] = 1
+        elif request.offset == TASKS_CALIBRATEOFFSET:
+            registers[EVENTS_CALIBRATEDONE] = 1
+elif request.isRead:
+    request.value = registers.get(request.offset, 0)
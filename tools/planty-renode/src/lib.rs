@@ -0,0 +1,338 @@
+//! Boots the unmodified `08-ble-watering` ELF in Renode's nRF52 emulation and
+//! drives it like the hardware would: moisture and supply readings through a
+//! simulated SAADC, button presses as GPIO levels. The tests check the pump
+//! pin and the defmt log, which is read out of RTT and decoded by
+//! `defmt-print`.
+//!
+//! The platform description and the SAADC model are in `platforms/`. Time in
+//! the emulation only moves in `Board::run_for`, so a scenario runs the same
+//! way every time however loaded the machine running it is.
+//!
+//! Needs `renode` and `defmt-print` on the `PATH`, or in `RENODE` and
+//! `DEFMT_PRINT`, the S140 7.3.0 hex in `SOFTDEVICE_HEX`, and a release
+//! build of the firmware, or its path in `PLANTY_ELF`.
+
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use monitor::Monitor;
+use rtt::Rtt;
+
+mod monitor;
+mod rtt;
+
+/// How much emulated time passes between two reads of the RTT buffer. defmt-rtt
+/// drops what doesn't fit into its 1 KiB buffer, so this has to be short
+/// enough for the firmware not to fill it in between.
+const SLICE: Duration = Duration::from_millis(50);
+
+/// GPIO port 0, which has every pin the firmware uses.
+const GPIO0: &str = "sysbus.gpio0";
+const GPIO0_OUT: u32 = 0x5000_0504;
+const PUMP_PIN: u32 = 3;
+
+const SAADC_INPUTS: u32 = 0x4000_7F00;
+/// `CH[n].PSELP` of the moisture probe on P0.04, and of VDD.
+const PSELP_AIN2: u32 = 3;
+const PSELP_VDD: u32 = 9;
+/// 12 bit results with the default 1/6 gain and 0.6 V reference.
+const FULL_SCALE_MILLIVOLTS: u32 = 3600;
+const FULL_SCALE_COUNTS: u32 = 1 << 12;
+
+/// Where the MBR looks for a bootloader. Erased, so it starts the SoftDevice,
+/// which starts the application right behind it.
+const UICR_BOOTLOADER: u32 = 0x1000_1014;
+const UICR_MBR_PARAMS: u32 = 0x1000_1018;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Elf(object::Error),
+    NoRtt,
+    Missing(&'static str),
+    Command { command: String, output: String },
+    Timeout(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{error}"),
+            Error::Elf(error) => write!(f, "failed to parse ELF: {error}"),
+            Error::NoRtt => write!(
+                f,
+                "ELF has no RTT control block, is it built with defmt-rtt?"
+            ),
+            Error::Missing(variable) => write!(f, "{variable} is not set"),
+            Error::Command { command, output } => write!(f, "`{command}` failed: {output}"),
+            Error::Timeout(line) => write!(f, "timed out waiting for a log line with {line:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<object::Error> for Error {
+    fn from(error: object::Error) -> Self {
+        Error::Elf(error)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub renode: PathBuf,
+    pub defmt_print: PathBuf,
+    pub elf: PathBuf,
+    pub softdevice: PathBuf,
+}
+
+impl Config {
+    /// Takes the paths from the environment, as described at the top.
+    pub fn from_env() -> Result<Self, Error> {
+        let path = |variable, default: &str| {
+            env::var_os(variable).map_or_else(|| PathBuf::from(default), PathBuf::from)
+        };
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        Ok(Self {
+            renode: path("RENODE", "renode"),
+            defmt_print: path("DEFMT_PRINT", "defmt-print"),
+            elf: env::var_os("PLANTY_ELF").map_or_else(
+                || workspace.join("target/thumbv7em-none-eabihf/release/ble-watering"),
+                PathBuf::from,
+            ),
+            softdevice: env::var_os("SOFTDEVICE_HEX")
+                .map(PathBuf::from)
+                .ok_or(Error::Missing("SOFTDEVICE_HEX"))?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+}
+
+impl Button {
+    fn pin(self) -> u32 {
+        match self {
+            Button::A => 14,
+            Button::B => 23,
+        }
+    }
+}
+
+/// A micro:bit running the firmware in Renode.
+pub struct Board {
+    renode: Child,
+    monitor: Monitor,
+    rtt: Rtt,
+    decoder: Child,
+    /// Raw RTT bytes for `defmt-print`.
+    frames: ChildStdin,
+    logs: Arc<Mutex<Vec<String>>>,
+    /// Log lines already returned by `wait_for_log`.
+    seen: usize,
+}
+
+impl Board {
+    /// Starts Renode, loads the SoftDevice and the firmware and lets it run
+    /// until it has logged something. The soil starts out wet, the supply
+    /// good and both buttons released.
+    pub fn boot(config: &Config) -> Result<Self, Error> {
+        let elf = fs::read(&config.elf)?;
+        let rtt = Rtt::find(&elf)?;
+
+        let port = TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port();
+        let platforms = Path::new(env!("CARGO_MANIFEST_DIR")).join("platforms");
+        let renode = Command::new(&config.renode)
+            .args(["--disable-xwt", "--plain", "--port", &port.to_string()])
+            // `saadc.py` is looked up relative to here
+            .current_dir(&platforms)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let mut decoder = Command::new(&config.defmt_print)
+            .arg("-e")
+            .arg(&config.elf)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let frames = decoder.stdin.take().expect("stdin is piped");
+        let lines = BufReader::new(decoder.stdout.take().expect("stdout is piped")).lines();
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let collected = logs.clone();
+        thread::spawn(move || {
+            for line in lines.map_while(Result::ok) {
+                println!("{line}");
+                collected.lock().unwrap().push(line);
+            }
+        });
+
+        let mut board = Self {
+            renode,
+            monitor: Monitor::connect(port)?,
+            rtt,
+            decoder,
+            frames,
+            logs,
+            seen: 0,
+        };
+        board.load(&platforms.join("microbit.repl"), config)?;
+        board.set_moisture(1500)?;
+        board.set_supply(3000)?;
+        board.set_button(Button::A, false)?;
+        board.set_button(Button::B, false)?;
+        board.wait_for_log("", Duration::from_secs(5))?;
+        Ok(board)
+    }
+
+    fn load(&mut self, platform: &Path, config: &Config) -> Result<(), Error> {
+        self.command("mach create \"planty\"")?;
+        self.command(&format!(
+            "machine LoadPlatformDescription @{}",
+            platform.display()
+        ))?;
+        self.command(&format!("sysbus LoadHEX @{}", config.softdevice.display()))?;
+        self.command(&format!("sysbus LoadELF @{}", config.elf.display()))?;
+        for address in [UICR_BOOTLOADER, UICR_MBR_PARAMS] {
+            self.command(&format!("sysbus WriteDoubleWord {address:#x} 0xffffffff"))?;
+        }
+
+        // Boot through the MBR like the hardware, rather than jumping
+        // straight into the application as `LoadELF` sets up
+        let sp = rtt::read_word(&mut self.monitor, 0x0)?;
+        let pc = rtt::read_word(&mut self.monitor, 0x4)?;
+        self.command("cpu VectorTableOffset 0x0")?;
+        self.command(&format!("cpu SP {sp:#x}"))?;
+        self.command(&format!("cpu PC {pc:#x}"))?;
+        Ok(())
+    }
+
+    pub fn command(&mut self, command: &str) -> Result<String, Error> {
+        self.monitor.command(command)
+    }
+
+    /// Sets the moisture probe reading, in SAADC counts. Lower is wetter.
+    pub fn set_moisture(&mut self, counts: u16) -> Result<(), Error> {
+        self.set_input(PSELP_AIN2, counts.into())
+    }
+
+    pub fn set_supply(&mut self, millivolts: u16) -> Result<(), Error> {
+        self.set_input(
+            PSELP_VDD,
+            u32::from(millivolts) * FULL_SCALE_COUNTS / FULL_SCALE_MILLIVOLTS,
+        )
+    }
+
+    fn set_input(&mut self, pselp: u32, counts: u32) -> Result<(), Error> {
+        let address = SAADC_INPUTS + 4 * pselp;
+        self.command(&format!("sysbus WriteDoubleWord {address:#x} {counts}"))?;
+        Ok(())
+    }
+
+    /// The buttons pull low when pressed.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> Result<(), Error> {
+        self.command(&format!("{GPIO0} OnGPIO {} {}", button.pin(), !pressed))?;
+        Ok(())
+    }
+
+    pub fn pump_on(&mut self) -> Result<bool, Error> {
+        let out = rtt::read_word(&mut self.monitor, GPIO0_OUT)?;
+        Ok(out & (1 << PUMP_PIN) != 0)
+    }
+
+    /// Lets `duration` of emulated time pass, collecting the log as it goes.
+    pub fn run_for(&mut self, duration: Duration) -> Result<(), Error> {
+        let mut left = duration;
+        while !left.is_zero() {
+            let slice = left.min(SLICE);
+            self.step(slice)?;
+            left -= slice;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, slice: Duration) -> Result<(), Error> {
+        self.command(&format!("emulation RunFor \"{}\"", time_interval(slice)))?;
+        let bytes = self.rtt.drain(&mut self.monitor)?;
+        if !bytes.is_empty() {
+            self.frames.write_all(&bytes)?;
+            self.frames.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Runs until a log line containing `needle` shows up that no earlier
+    /// call returned, for at most `timeout` of emulated time.
+    pub fn wait_for_log(&mut self, needle: &str, timeout: Duration) -> Result<String, Error> {
+        let mut waited = Duration::ZERO;
+        loop {
+            // `defmt-print` decodes on its own time
+            for _ in 0..10 {
+                if let Some(line) = self.find_log(needle) {
+                    return Ok(line);
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            if waited >= timeout {
+                return Err(Error::Timeout(needle.to_string()));
+            }
+            self.step(SLICE)?;
+            waited += SLICE;
+        }
+    }
+
+    fn find_log(&mut self, needle: &str) -> Option<String> {
+        let logs = self.logs.lock().unwrap();
+        let (index, line) = logs
+            .iter()
+            .enumerate()
+            .skip(self.seen)
+            .find(|(_, line)| line.contains(needle))?;
+        self.seen = index + 1;
+        Some(line.clone())
+    }
+
+    /// Every decoded log line so far.
+    pub fn logs(&self) -> Vec<String> {
+        self.logs.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Board {
+    fn drop(&mut self) {
+        let _ = self.monitor.command("quit");
+        let _ = self.renode.kill();
+        let _ = self.renode.wait();
+        let _ = self.decoder.kill();
+        let _ = self.decoder.wait();
+    }
+}
+
+/// `hh:mm:ss.ffffff`, as Renode takes time intervals.
+fn time_interval(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:06}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        duration.subsec_micros()
+    )
+}
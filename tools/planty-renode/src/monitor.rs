@@ -0,0 +1,130 @@
+//! Renode's monitor, driven over its telnet port.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+/// How long Renode gets to open its monitor port after starting.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Telnet "interpret as command", which starts a negotiation sequence.
+const IAC: u8 = 0xff;
+
+pub struct Monitor {
+    stream: TcpStream,
+    pending: Vec<u8>,
+}
+
+impl Monitor {
+    /// Connects to the monitor on `port` and waits for the first prompt.
+    pub fn connect(port: u16) -> Result<Self, Error> {
+        let started = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) if started.elapsed() < CONNECT_TIMEOUT => {
+                    thread::sleep(Duration::from_millis(100))
+                }
+                Err(error) => return Err(Error::Io(error)),
+            }
+        };
+        let mut monitor = Self {
+            stream,
+            pending: Vec::new(),
+        };
+        monitor.read_until_prompt()?;
+        Ok(monitor)
+    }
+
+    /// Runs a monitor command and returns what it printed, without the echo
+    /// and the prompt. Renode reports failed commands in the output, so any
+    /// line starting with "There was an error" is turned into an error.
+    pub fn command(&mut self, command: &str) -> Result<String, Error> {
+        self.stream.write_all(command.as_bytes())?;
+        self.stream.write_all(b"\n")?;
+        let output = self.read_until_prompt()?;
+
+        let output: Vec<&str> = output
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && line.trim() != command)
+            .collect();
+        if output
+            .iter()
+            .any(|line| line.starts_with("There was an error") || line.starts_with("Could not"))
+        {
+            return Err(Error::Command {
+                command: command.to_string(),
+                output: output.join("\n"),
+            });
+        }
+        Ok(output.join("\n"))
+    }
+
+    /// Reads up to and including the next prompt, like `(planty) `, and
+    /// returns everything before it.
+    fn read_until_prompt(&mut self) -> Result<String, Error> {
+        let mut chunk = [0; 4096];
+        loop {
+            let text = String::from_utf8_lossy(&self.pending).into_owned();
+            if let Some(end) = prompt_start(&text) {
+                self.pending.clear();
+                return Ok(text[..end].to_string());
+            }
+
+            let len = self.stream.read(&mut chunk)?;
+            if len == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            strip_telnet(&chunk[..len], &mut self.pending);
+        }
+    }
+}
+
+/// Where the trailing prompt starts, if `text` ends in one.
+fn prompt_start(text: &str) -> Option<usize> {
+    let start = text.rfind('\n').map_or(0, |newline| newline + 1);
+    let last = &text[start..];
+    let name = last.strip_prefix('(')?.strip_suffix(") ")?;
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    is_name.then_some(start)
+}
+
+/// Drops telnet negotiation and terminal control bytes.
+fn strip_telnet(bytes: &[u8], out: &mut Vec<u8>) {
+    let mut bytes = bytes.iter().copied();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            IAC => {
+                // Command and option
+                bytes.next();
+                bytes.next();
+            }
+            b'\r' | 0 => {}
+            _ => out.push(byte),
+        }
+    }
+}
+
+/// The value printed by commands like `sysbus ReadDoubleWord`.
+pub fn parse_word(output: &str) -> Option<u32> {
+    let hex = output.trim().strip_prefix("0x")?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// The bytes printed by `sysbus ReadBytes`, as `0x..` tokens.
+pub fn parse_bytes(output: &str) -> Vec<u8> {
+    output
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(|token| token.strip_prefix("0x"))
+        .filter(|hex| hex.len() == 2)
+        .filter_map(|hex| u8::from_str_radix(hex, 16).ok())
+        .collect()
+}
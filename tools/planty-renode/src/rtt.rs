@@ -0,0 +1,96 @@
+//! Reads the defmt RTT channel out of the emulated RAM, the way a debug probe
+//! would.
+//!
+//! The control block, `_SEGGER_RTT`, starts with a 16 byte id and the number
+//! of up and down channels, followed by one descriptor per up channel:
+//!
+//! | offset | field                                  |
+//! |--------|----------------------------------------|
+//! | 0      | name pointer                           |
+//! | 4      | buffer pointer                         |
+//! | 8      | buffer size                            |
+//! | 12     | write offset, advanced by the firmware |
+//! | 16     | read offset, advanced by us            |
+//! | 20     | flags                                  |
+//!
+//! defmt-rtt logs to up channel 0.
+
+use object::{elf::FileHeader32, read::elf::ElfFile, Endianness, Object, ObjectSymbol};
+
+use crate::{
+    monitor::{self, Monitor},
+    Error,
+};
+
+const CONTROL_BLOCK: &str = "_SEGGER_RTT";
+/// Offset of up channel 0's descriptor in the control block.
+const UP_CHANNEL: u32 = 24;
+const BUFFER: u32 = 4;
+const SIZE: u32 = 8;
+const WRITE: u32 = 12;
+const READ: u32 = 16;
+
+pub struct Rtt {
+    channel: u32,
+}
+
+impl Rtt {
+    /// Finds the control block in the firmware ELF.
+    pub fn find(elf: &[u8]) -> Result<Self, Error> {
+        let file = ElfFile::<FileHeader32<Endianness>>::parse(elf)?;
+        let symbol = file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(CONTROL_BLOCK))
+            .ok_or(Error::NoRtt)?;
+        Ok(Self {
+            channel: symbol.address() as u32 + UP_CHANNEL,
+        })
+    }
+
+    /// Takes whatever the firmware wrote since the last call. Returns nothing
+    /// until the firmware has set up the channel.
+    pub fn drain(&self, monitor: &mut Monitor) -> Result<Vec<u8>, Error> {
+        let buffer = read_word(monitor, self.channel + BUFFER)?;
+        let size = read_word(monitor, self.channel + SIZE)?;
+        let write = read_word(monitor, self.channel + WRITE)?;
+        let read = read_word(monitor, self.channel + READ)?;
+        if buffer == 0 || size == 0 || write >= size || read >= size || write == read {
+            return Ok(Vec::new());
+        }
+
+        // Up to the write offset, or to the end of the buffer and around
+        let mut bytes = Vec::new();
+        let mut start = read;
+        if write < read {
+            bytes.extend(read_bytes(monitor, buffer + read, size - read)?);
+            start = 0;
+        }
+        bytes.extend(read_bytes(monitor, buffer + start, write - start)?);
+
+        monitor.command(&format!(
+            "sysbus WriteDoubleWord {:#x} {:#x}",
+            self.channel + READ,
+            write
+        ))?;
+        Ok(bytes)
+    }
+}
+
+pub fn read_word(monitor: &mut Monitor, address: u32) -> Result<u32, Error> {
+    let command = format!("sysbus ReadDoubleWord {address:#x}");
+    let output = monitor.command(&command)?;
+    monitor::parse_word(&output).ok_or(Error::Command { command, output })
+}
+
+fn read_bytes(monitor: &mut Monitor, address: u32, len: u32) -> Result<Vec<u8>, Error> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let command = format!("sysbus ReadBytes {address:#x} {len}");
+    let output = monitor.command(&command)?;
+    let bytes = monitor::parse_bytes(&output);
+    if bytes.len() != len as usize {
+        return Err(Error::Command { command, output });
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,72 @@
+//! Scenarios on the real firmware in Renode. They need Renode, `defmt-print`,
+//! the SoftDevice and a release build of the firmware (see the crate docs),
+//! so they only run when asked for:
+//!
+//! ```sh
+//! cargo build --release -p ble-watering
+//! cd tools
+//! SOFTDEVICE_HEX=... cargo test -p planty-renode -- --ignored
+//! ```
+
+use std::time::Duration;
+
+use planty_renode::{Board, Button, Config};
+
+/// Well above the firmware's default threshold of 2000.
+const DRY: u16 = 2800;
+const WET: u16 = 1500;
+
+fn boot() -> Board {
+    let config = Config::from_env().unwrap();
+    Board::boot(&config).unwrap()
+}
+
+#[test]
+#[ignore = "needs Renode"]
+fn dry_soil_gets_a_pump_pulse() {
+    let mut board = boot();
+    board.set_moisture(DRY).unwrap();
+
+    // The first measurement is due 10 s after boot
+    board
+        .wait_for_log("Soil is dry, watering for 5000 ms", Duration::from_secs(12))
+        .unwrap();
+    board
+        .wait_for_log("Pump on", Duration::from_secs(1))
+        .unwrap();
+    assert!(board.pump_on().unwrap());
+
+    board.run_for(Duration::from_secs(4)).unwrap();
+    assert!(board.pump_on().unwrap());
+    board
+        .wait_for_log("Pump off", Duration::from_secs(2))
+        .unwrap();
+    assert!(!board.pump_on().unwrap());
+}
+
+#[test]
+#[ignore = "needs Renode"]
+fn holding_a_waters_until_released() {
+    let mut board = boot();
+    board.set_moisture(WET).unwrap();
+    board.run_for(Duration::from_secs(1)).unwrap();
+
+    board.set_button(Button::A, true).unwrap();
+    board
+        .wait_for_log("Gesture Long(A)", Duration::from_secs(2))
+        .unwrap();
+    board
+        .wait_for_log("Pump on", Duration::from_secs(1))
+        .unwrap();
+    board.run_for(Duration::from_secs(2)).unwrap();
+    assert!(board.pump_on().unwrap());
+
+    board.set_button(Button::A, false).unwrap();
+    board
+        .wait_for_log("Gesture LongEnd(A)", Duration::from_secs(1))
+        .unwrap();
+    board
+        .wait_for_log("Pump off", Duration::from_secs(1))
+        .unwrap();
+    assert!(!board.pump_on().unwrap());
+}